@@ -14,8 +14,10 @@ use log::{debug, warn};
 use walkdir::WalkDir;
 
 use crate::{
-    action_step, ignore_step, rust::is_current_target_runtime, Context, Error, ErrorContext,
-    Package, Result,
+    action_step, ignore_step,
+    reporter::{self, Subject},
+    rust::is_current_target_runtime,
+    Context, Error, ErrorContext, Package, Result,
 };
 
 use super::AwsLambdaMetadata;
@@ -56,6 +58,8 @@ impl<'g> AwsLambdaDistTarget<'g> {
 
         self.build_zip_archive()?;
 
+        reporter::artifact(self.subject(), self.archive_path().display().to_string());
+
         Ok(())
     }
 
@@ -98,12 +102,7 @@ impl<'g> AwsLambdaDistTarget<'g> {
             let shared_config = aws_config::from_env().region(region_provider).load().await;
             let client = aws_sdk_s3::Client::new(&shared_config);
 
-            let s3_key = format!(
-                "{}{}/v{}.zip",
-                &self.metadata.s3_bucket_prefix,
-                self.package.name(),
-                self.package.version()
-            );
+            let s3_key = self.s3_key();
 
             if self.context().options().force {
                 debug!("`--force` specified: not checking for the archive existence on S3 before uploading");
@@ -164,6 +163,8 @@ impl<'g> AwsLambdaDistTarget<'g> {
                         &s3_bucket
                     ))
                 )?;
+
+                reporter::artifact(self.subject(), format!("s3://{}/{}", &s3_bucket, &s3_key));
             }
 
             Ok(())
@@ -253,7 +254,7 @@ impl<'g> AwsLambdaDistTarget<'g> {
     }
 
     fn build_binaries(&self) -> Result<HashMap<String, PathBuf>> {
-        let ws = self.context().workspace()?;
+        let ws = self.context().workspace();
         let mut compile_options = CompileOptions::new(ws.config(), CompileMode::Build).unwrap();
 
         compile_options.spec =
@@ -268,7 +269,7 @@ impl<'g> AwsLambdaDistTarget<'g> {
                 )];
         }
 
-        compile(&ws, &compile_options)
+        compile(ws, &compile_options)
             .map(|compilation| {
                 compilation
                     .binaries
@@ -320,6 +321,24 @@ impl<'g> AwsLambdaDistTarget<'g> {
         Ok(())
     }
 
+    fn subject(&self) -> Subject {
+        Subject::dist_target(self.package, &self.name, "aws-lambda")
+    }
+
+    /// Get the S3 URL the archive is uploaded to.
+    pub fn destination(&self) -> Result<String> {
+        Ok(format!("s3://{}/{}", self.s3_bucket()?, self.s3_key()))
+    }
+
+    fn s3_key(&self) -> String {
+        format!(
+            "{}{}/v{}.zip",
+            &self.metadata.s3_bucket_prefix,
+            self.package.name(),
+            self.package.version()
+        )
+    }
+
     fn s3_bucket(&self) -> Result<String> {
         match &self.metadata.s3_bucket {
             Some(s3_bucket) => Ok(s3_bucket.clone()),
@@ -0,0 +1,219 @@
+//! Build configuration inputs that affect the binaries produced for a package
+//! beyond its own sources.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+
+use crate::{
+    sources::{relative_path, DigestOptions, Sources},
+    Error, ErrorContext, Result,
+};
+use log::debug;
+use serde::Serialize;
+
+/// The toolchain files, by order of precedence.
+const TOOLCHAIN_FILE_NAMES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+/// The Cargo configuration files, relative to the directory they apply to.
+const CARGO_CONFIG_FILE_NAMES: &[&str] = &[".cargo/config", ".cargo/config.toml"];
+
+/// The build configuration of a package.
+///
+/// This includes the toolchain file and the Cargo configuration files that
+/// apply to the package, the profiles of the workspace root manifest and the
+/// features the distribution targets are built with.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BuildConfig {
+    /// The digests of the configuration files that were picked up, keyed by
+    /// their path relative to the workspace root.
+    files: BTreeMap<String, String>,
+    /// The `[profile.*]` sections of the workspace root manifest.
+    profiles: Option<toml::Value>,
+    /// The features enabled when building the distribution targets.
+    ///
+    /// Distribution targets are always built with the default features.
+    features: BTreeSet<String>,
+}
+
+impl BuildConfig {
+    pub(crate) fn new(
+        workspace_root: &Path,
+        package_manifest_path: &Path,
+        options: DigestOptions,
+    ) -> Result<Self> {
+        let package_root = package_manifest_path.parent().unwrap();
+        let mut files = BTreeMap::new();
+        let mut toolchain_file_found = false;
+
+        // Like Cargo and rustup, we consider the package root and all its
+        // ancestors. We stop at the workspace root though, as anything above
+        // it is specific to the machine and not part of the repository.
+        for dir in package_root
+            .ancestors()
+            .take_while(|dir| dir.starts_with(workspace_root))
+        {
+            // Only the nearest toolchain file is ever used.
+            if !toolchain_file_found {
+                for file_name in TOOLCHAIN_FILE_NAMES {
+                    if Self::add_file(&mut files, workspace_root, &dir.join(file_name), options)? {
+                        toolchain_file_found = true;
+                        break;
+                    }
+                }
+            }
+
+            // Cargo merges all the configuration files it finds.
+            for file_name in CARGO_CONFIG_FILE_NAMES {
+                Self::add_file(&mut files, workspace_root, &dir.join(file_name), options)?;
+            }
+        }
+
+        let profiles = read_manifest(&workspace_root.join("Cargo.toml"))?
+            .get("profile")
+            .cloned();
+
+        let features = read_manifest(package_manifest_path)?
+            .get("features")
+            .cloned()
+            .map(|features| {
+                features
+                    .try_into::<BTreeMap<String, Vec<String>>>()
+                    .map_err(|err| Error::new("failed to parse features").with_source(err))
+            })
+            .transpose()?
+            .map(|features| enabled_features(&features, ["default"]))
+            .unwrap_or_default();
+
+        Ok(Self {
+            files,
+            profiles,
+            features,
+        })
+    }
+
+    /// Get the build configuration inputs that were picked up.
+    pub(crate) fn inputs(&self) -> Vec<BuildConfigInput<'_>> {
+        self.files
+            .keys()
+            .map(|path| BuildConfigInput::File(path))
+            .chain(
+                self.profiles
+                    .as_ref()
+                    .and_then(toml::Value::as_table)
+                    .into_iter()
+                    .flat_map(|profiles| profiles.keys())
+                    .map(|name| BuildConfigInput::Profile(name)),
+            )
+            .chain(
+                self.features
+                    .iter()
+                    .map(|feature| BuildConfigInput::Feature(feature)),
+            )
+            .collect()
+    }
+
+    fn add_file(
+        files: &mut BTreeMap<String, String>,
+        workspace_root: &Path,
+        path: &Path,
+        options: DigestOptions,
+    ) -> Result<bool> {
+        if !path.is_file() {
+            return Ok(false);
+        }
+
+        debug!("Picked up build configuration file `{}`", path.display());
+
+        files.insert(
+            relative_path(workspace_root, path)?,
+            Sources::digest_file(path, options)?,
+        );
+
+        Ok(true)
+    }
+}
+
+/// A build configuration input.
+pub(crate) enum BuildConfigInput<'a> {
+    File(&'a str),
+    Profile(&'a str),
+    Feature(&'a str),
+}
+
+impl Display for BuildConfigInput<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file {path}"),
+            Self::Profile(name) => write!(f, "profile {name}"),
+            Self::Feature(name) => write!(f, "feature {name}"),
+        }
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<toml::Value> {
+    let data = std::fs::read_to_string(path)
+        .map_err(Error::from_source)
+        .with_context(format!("failed to read manifest `{}`", path.display()))?;
+
+    toml::from_str(&data)
+        .map_err(Error::from_source)
+        .with_context(format!("failed to parse manifest `{}`", path.display()))
+}
+
+/// Resolve the set of features enabled by the specified root features.
+///
+/// Entries that are not features of the package - like optional dependencies
+/// or features of dependencies - are kept as-is.
+fn enabled_features<'a>(
+    features: &BTreeMap<String, Vec<String>>,
+    roots: impl IntoIterator<Item = &'a str>,
+) -> BTreeSet<String> {
+    let mut enabled = BTreeSet::new();
+    let mut pending: Vec<&str> = roots
+        .into_iter()
+        .filter(|root| features.contains_key(*root))
+        .collect();
+
+    while let Some(feature) = pending.pop() {
+        if enabled.insert(feature.to_string()) {
+            if let Some(entries) = features.get(feature) {
+                pending.extend(entries.iter().map(String::as_str));
+            }
+        }
+    }
+
+    enabled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enabled_features() {
+        let features: BTreeMap<String, Vec<String>> = [
+            ("default", vec!["std", "serde/derive"]),
+            ("std", vec!["dep:libc"]),
+            ("extra", vec!["std"]),
+        ]
+        .into_iter()
+        .map(|(name, entries)| {
+            (
+                name.to_string(),
+                entries.into_iter().map(ToString::to_string).collect(),
+            )
+        })
+        .collect();
+
+        assert_eq!(
+            enabled_features(&features, ["default"])
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["default", "dep:libc", "serde/derive", "std"]
+        );
+        assert!(enabled_features(&BTreeMap::new(), ["default"]).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! Explanations of why packages are considered changed.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// A change that directly affects a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DirectChange {
+    /// Some files of the package changed, listed relative to the package root.
+    Files { files: Vec<String> },
+    /// The manifest of the package changed.
+    Manifest,
+    /// The resolved dependencies of the package changed in the lockfile.
+    Lockfile,
+    /// The profiles of the workspace root manifest changed.
+    Profiles,
+    /// Files matched by a workspace trigger changed, listed relative to the
+    /// workspace root.
+    Trigger { pattern: String, files: Vec<String> },
+}
+
+impl Display for DirectChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Files { files } => write!(f, "{}", files.join(", ")),
+            Self::Manifest => write!(f, "Cargo.toml"),
+            Self::Lockfile => write!(f, "Cargo.lock"),
+            Self::Profiles => write!(f, "workspace profiles"),
+            Self::Trigger { pattern, files } => {
+                write!(f, "{} via trigger `{}`", files.join(", "), pattern)
+            }
+        }
+    }
+}
+
+/// The reason why a package is considered changed.
+///
+/// A package either changed directly, or was pulled in through one of its
+/// dependencies, in which case the shortest dependency path to a package that
+/// changed directly is reported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangeExplanation {
+    pub package: String,
+    /// The dependencies through which the package was pulled in, from its
+    /// direct dependency to the package that changed directly.
+    ///
+    /// Empty if the package changed directly.
+    pub dependency_path: Vec<String>,
+    /// The direct changes of the last package of the dependency path - or of
+    /// the package itself.
+    pub changes: Vec<DirectChange>,
+}
+
+impl Display for ChangeExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.package)?;
+
+        for dependency in &self.dependency_path {
+            write!(f, " <- {dependency}")?;
+        }
+
+        write!(
+            f,
+            " ({})",
+            self.changes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_explanation_display() {
+        let explanation = ChangeExplanation {
+            package: "service".to_string(),
+            dependency_path: vec!["api".to_string(), "core".to_string()],
+            changes: vec![DirectChange::Files {
+                files: vec!["src/lib.rs".to_string()],
+            }],
+        };
+
+        assert_eq!(
+            explanation.to_string(),
+            "service <- api <- core (src/lib.rs)"
+        );
+
+        let explanation = ChangeExplanation {
+            package: "core".to_string(),
+            dependency_path: vec![],
+            changes: vec![
+                DirectChange::Files {
+                    files: vec!["src/lib.rs".to_string(), "build.rs".to_string()],
+                },
+                DirectChange::Manifest,
+            ],
+        };
+
+        assert_eq!(
+            explanation.to_string(),
+            "core (src/lib.rs, build.rs, Cargo.toml)"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Reporting of the progress of a run in the native formats of CI services.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+use crate::reporter::{
+    Event, HumanReporter, Operation, OperationOutcome, Reporter, Status, Subject, Verbosity,
+};
+
+const GITHUB_STEP_SUMMARY_ENV_VAR_NAME: &str = "GITHUB_STEP_SUMMARY";
+
+/// A CI service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiProvider {
+    GitHubActions,
+    GitLabCi,
+}
+
+impl CiProvider {
+    /// Detect the CI service the process runs under, if any.
+    pub fn detect() -> Option<Self> {
+        let is_set = |name| std::env::var(name).is_ok_and(|value| value == "true");
+
+        if is_set("GITHUB_ACTIONS") {
+            Some(Self::GitHubActions)
+        } else if is_set("GITLAB_CI") {
+            Some(Self::GitLabCi)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reports events as text, with the extensions of a CI service.
+///
+/// The output of the operations on each package is wrapped in a collapsible
+/// section, errors and skipped publications are annotated and, on GitHub
+/// Actions, the summary of the runs that performed operations is written as a
+/// markdown table to the job summary.
+///
+/// Sections are only printed with the normal verbosity, and skipped
+/// publications are not annotated when silent.
+pub struct CiReporter {
+    provider: CiProvider,
+    verbosity: Verbosity,
+    human: HumanReporter,
+    sections: AtomicUsize,
+}
+
+impl CiReporter {
+    pub fn new(provider: CiProvider, verbosity: Verbosity) -> Self {
+        Self {
+            provider,
+            verbosity,
+            human: HumanReporter::new(verbosity),
+            sections: AtomicUsize::new(0),
+        }
+    }
+
+    fn start_section(&self, title: &str) {
+        if self.verbosity != Verbosity::Normal {
+            return;
+        }
+
+        match self.provider {
+            CiProvider::GitHubActions => println!("::group::{}", escape_data(title)),
+            CiProvider::GitLabCi => {
+                let index = self.sections.fetch_add(1, Ordering::SeqCst);
+
+                println!(
+                    "\x1b[0Ksection_start:{}:monorepo_{}[collapsed=true]\r\x1b[0K{}",
+                    unix_timestamp(),
+                    index,
+                    title
+                );
+            }
+        }
+    }
+
+    fn end_section(&self) {
+        if self.verbosity != Verbosity::Normal {
+            return;
+        }
+
+        match self.provider {
+            CiProvider::GitHubActions => println!("::endgroup::"),
+            CiProvider::GitLabCi => {
+                let index = self.sections.load(Ordering::SeqCst).saturating_sub(1);
+
+                println!(
+                    "\x1b[0Ksection_end:{}:monorepo_{}\r\x1b[0K",
+                    unix_timestamp(),
+                    index
+                );
+            }
+        }
+    }
+
+    fn annotate(&self, level: &str, title: &str, message: &str) {
+        match self.provider {
+            CiProvider::GitHubActions => println!(
+                "::{} title={}::{}",
+                level,
+                escape_property(title),
+                escape_data(message)
+            ),
+            // GitLab CI has no annotations: the error is printed by the
+            // binary and warnings are already printed as steps.
+            CiProvider::GitLabCi => {}
+        }
+    }
+
+    fn write_step_summary(
+        &self,
+        success: bool,
+        duration_secs: f64,
+        operations: &[OperationOutcome],
+    ) {
+        // Commands that perform no operations, like `list` or `hash`, have
+        // nothing to summarize.
+        if self.provider != CiProvider::GitHubActions || operations.is_empty() {
+            return;
+        }
+
+        let path = match std::env::var_os(GITHUB_STEP_SUMMARY_ENV_VAR_NAME) {
+            Some(path) => PathBuf::from(path),
+            None => return,
+        };
+
+        let summary = step_summary(success, duration_secs, operations);
+
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(summary.as_bytes()));
+
+        if let Err(err) = result {
+            warn!(
+                "Failed to write the job summary to `{}`: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+impl Reporter for CiReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::Started {
+                subject:
+                    subject @ Subject {
+                        dist_target: None, ..
+                    },
+                operation,
+            } => self.start_section(&format!(
+                "{} {} v{}",
+                operation, subject.package, subject.version
+            )),
+            Event::Ended(
+                outcome @ OperationOutcome {
+                    subject:
+                        Subject {
+                            dist_target: None, ..
+                        },
+                    ..
+                },
+            ) => {
+                self.end_section();
+
+                if outcome.status == Status::Skipped && self.verbosity != Verbosity::Silent {
+                    self.annotate(
+                        "warning",
+                        &format!(
+                            "{} of {} skipped",
+                            outcome.operation, outcome.subject.package
+                        ),
+                        outcome.reason.as_deref().unwrap_or_default(),
+                    );
+                }
+            }
+            Event::Error {
+                description,
+                source,
+                explanation,
+                ..
+            } => {
+                let mut message = explanation.clone().unwrap_or_default();
+
+                if let Some(source) = source {
+                    if !message.is_empty() {
+                        message.push_str("\n\n");
+                    }
+
+                    write!(message, "Caused by: {source}").unwrap();
+                }
+
+                self.annotate("error", description, &message);
+            }
+            Event::Summary {
+                success,
+                duration_secs,
+                operations,
+            } => self.write_step_summary(*success, *duration_secs, operations),
+            event => self.human.report(event),
+        }
+    }
+}
+
+/// Render the outcomes of the operations of a run as a markdown table.
+fn step_summary(success: bool, duration_secs: f64, operations: &[OperationOutcome]) -> String {
+    let packages: BTreeSet<_> = operations
+        .iter()
+        .map(|outcome| &outcome.subject.package)
+        .collect();
+
+    let dist_targets_count = |operation: Option<Operation>, status: Status| {
+        operations
+            .iter()
+            .filter(|outcome| {
+                outcome.subject.dist_target.is_some()
+                    && operation.is_none_or(|operation| outcome.operation == operation)
+                    && outcome.status == status
+            })
+            .count()
+    };
+
+    let mut summary = format!(
+        "### cargo monorepo {}\n\n\
+         {} package(s) processed in {:.2}s: {} distribution target(s) built, {} published, {} skipped, {} failed.\n\n",
+        if success { "succeeded" } else { "failed" },
+        packages.len(),
+        duration_secs,
+        dist_targets_count(Some(Operation::Build), Status::Finished),
+        dist_targets_count(Some(Operation::Publish), Status::Finished),
+        dist_targets_count(None, Status::Skipped),
+        dist_targets_count(None, Status::Failed),
+    );
+
+    summary.push_str(
+        "| Package | Distribution target | Operation | Status | Reason | Duration |\n|-|-|-|-|-|-|\n",
+    );
+
+    for outcome in operations {
+        writeln!(
+            summary,
+            "| {} v{} | {} | {} | {} | {} | {:.2}s |",
+            outcome.subject.package,
+            outcome.subject.version,
+            outcome
+                .subject
+                .dist_target
+                .as_ref()
+                .map(|dist_target| format!("{} ({})", dist_target.name, dist_target.type_name))
+                .unwrap_or_default(),
+            outcome.operation,
+            outcome.status,
+            escape_markdown_cell(outcome.reason.as_deref().unwrap_or_default()),
+            outcome.duration_secs
+        )
+        .unwrap();
+    }
+
+    summary.push('\n');
+    summary
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Escape the message of a GitHub Actions workflow command.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property of a GitHub Actions workflow command.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn escape_markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporter::DistTargetSubject;
+
+    #[test]
+    fn test_escape_property() {
+        assert_eq!(
+            escape_property("failed: 50%, really\nfailed"),
+            "failed%3A 50%25%2C really%0Afailed"
+        );
+    }
+
+    #[test]
+    fn test_write_step_summary_only_with_operations() {
+        let path = std::env::temp_dir().join(format!(
+            "cargo-monorepo-step-summary-{}.md",
+            std::process::id()
+        ));
+        std::fs::remove_file(&path).ok();
+        std::env::set_var(GITHUB_STEP_SUMMARY_ENV_VAR_NAME, &path);
+
+        let reporter = CiReporter::new(CiProvider::GitHubActions, Verbosity::Normal);
+
+        reporter.write_step_summary(true, 1.0, &[]);
+        assert!(!path.exists());
+
+        reporter.write_step_summary(
+            true,
+            1.0,
+            &[OperationOutcome {
+                subject: Subject {
+                    package: "service".to_string(),
+                    version: "0.1.0".to_string(),
+                    dist_target: None,
+                },
+                operation: Operation::Build,
+                status: Status::Finished,
+                reason: None,
+                duration_secs: 1.0,
+            }],
+        );
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("| service v0.1.0 |  | build | finished |  | 1.00s |"));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_step_summary() {
+        let subject = Subject {
+            package: "service".to_string(),
+            version: "0.1.0".to_string(),
+            dist_target: None,
+        };
+
+        let operations = [
+            OperationOutcome {
+                subject: Subject {
+                    dist_target: Some(DistTargetSubject {
+                        name: "service-image".to_string(),
+                        type_name: "docker".to_string(),
+                    }),
+                    ..subject.clone()
+                },
+                operation: Operation::Publish,
+                status: Status::Skipped,
+                reason: Some("Up-to-date image | exists".to_string()),
+                duration_secs: 1.5,
+            },
+            OperationOutcome {
+                subject,
+                operation: Operation::Publish,
+                status: Status::Finished,
+                reason: None,
+                duration_secs: 2.0,
+            },
+        ];
+
+        assert_eq!(
+            step_summary(true, 3.0, &operations),
+            "### cargo monorepo succeeded\n\n\
+             1 package(s) processed in 3.00s: 0 distribution target(s) built, 0 published, 1 skipped, 0 failed.\n\n\
+             | Package | Distribution target | Operation | Status | Reason | Duration |\n\
+             |-|-|-|-|-|-|\n\
+             | service v0.1.0 | service-image (docker) | publish | skipped | Up-to-date image \\| exists | 1.50s |\n\
+             | service v0.1.0 |  | publish | finished |  | 2.00s |\n\n"
+        );
+    }
+}
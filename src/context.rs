@@ -5,15 +5,33 @@ use git2::Repository;
 use guppy::graph::DependencyDirection;
 use itertools::Itertools;
 use log::debug;
-use std::{fmt::Display, path::PathBuf};
-
-use crate::{Error, Package, Result};
-
-#[derive(Default, Debug)]
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    change_explanation::{ChangeExplanation, DirectChange},
+    git::GitChanges,
+    hash::{HashAlgorithm, HashFormat, HashSource},
+    hash_cache::HashCache,
+    lockfile::Lockfile,
+    manifest_changes,
+    metadata::WorkspaceMetadata,
+    sources::{DigestOptions, GitFileModes},
+    triggers::Triggers,
+    Error, Package, Result,
+};
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Default, Debug, Clone)]
 pub struct Options {
     pub dry_run: bool,
     pub force: bool,
     pub verbose: bool,
+    pub no_cache: bool,
     pub mode: Mode,
 }
 
@@ -62,6 +80,7 @@ impl Display for Mode {
 pub struct ContextBuilder {
     manifest_path: Option<PathBuf>,
     options: Options,
+    hash_algorithm: Option<HashAlgorithm>,
 }
 
 impl ContextBuilder {
@@ -82,7 +101,7 @@ impl ContextBuilder {
         let manifest_path = std::fs::canonicalize(manifest_path)
             .map_err(|err| Error::new("could not find Cargo.toml").with_source(err))?;
 
-        Context::new(manifest_path, self.options)
+        Context::new(&manifest_path, self.options, self.hash_algorithm)
     }
 
     /// Specify the path to the manifest file to use.
@@ -100,14 +119,31 @@ impl ContextBuilder {
 
         self
     }
+
+    /// Specify the hash algorithm to use.
+    ///
+    /// If not called, the default is to use the algorithm configured in the
+    /// workspace metadata.
+    #[must_use]
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = Some(hash_algorithm);
+
+        self
+    }
 }
 /// A build context.
 #[derive(Debug)]
 pub struct Context {
-    manifest_path: PathBuf,
     options: Options,
-    config: cargo::util::Config,
+    workspace: cargo::core::Workspace<'static>,
     package_graph: guppy::graph::PackageGraph,
+    workspace_metadata: WorkspaceMetadata,
+    hash_algorithm: HashAlgorithm,
+    lockfile: Lockfile,
+    hash_cache: HashCache,
+    git_file_modes: GitFileModes,
+    /// The hashes computed so far, keyed by package and by algorithm.
+    hashes: RefCell<HashMap<(guppy::PackageId, HashAlgorithm), String>>,
 }
 
 impl Context {
@@ -116,21 +152,72 @@ impl Context {
         ContextBuilder::default()
     }
 
-    fn new(manifest_path: PathBuf, options: Options) -> Result<Self> {
+    fn new(
+        manifest_path: &Path,
+        options: Options,
+        hash_algorithm: Option<HashAlgorithm>,
+    ) -> Result<Self> {
         let config = cargo::util::config::Config::default()
             .map_err(|err| Error::new("failed to load Cargo configuration").with_source(err))?;
 
+        // Cargo workspaces borrow their configuration: the configuration lives
+        // as long as the process so that the workspace - and everything
+        // loaded through it - can be reused for the whole life of the context.
+        let config: &'static _ = Box::leak(Box::new(config));
+        let workspace = cargo::core::Workspace::new(manifest_path, config)
+            .map_err(|err| Error::new("failed to load Cargo workspace").with_source(err))?;
+
         let mut cmd = guppy::MetadataCommand::new();
-        cmd.manifest_path(&manifest_path);
+        cmd.manifest_path(manifest_path);
+
+        let cargo_metadata = cmd
+            .exec()
+            .map_err(|err| Error::new("failed to run `cargo metadata`").with_source(err))?;
 
-        let package_graph = guppy::graph::PackageGraph::from_command(&mut cmd)
+        let workspace_metadata = WorkspaceMetadata::new(&cargo_metadata)?;
+
+        let package_graph = cargo_metadata
+            .build_graph()
             .map_err(|err| Error::new("failed to parse package graph").with_source(err))?;
+        let hash_algorithm = hash_algorithm.unwrap_or(workspace_metadata.hash_algorithm);
+
+        debug!("Using hash algorithm: {}", hash_algorithm);
+
+        let lockfile = Lockfile::load(
+            &package_graph
+                .workspace()
+                .root()
+                .as_std_path()
+                .join("Cargo.lock"),
+        )?;
+
+        let hash_cache = if options.no_cache {
+            debug!("`--no-cache` specified: not using the hash cache");
+
+            HashCache::disabled()
+        } else {
+            let target_root = workspace.target_dir().into_path_unlocked();
+
+            HashCache::load(target_root.join("monorepo").join("hash-cache.json"))
+        };
+
+        // The file system has the executable bits of the files on Unix.
+        let git_file_modes = if cfg!(unix) {
+            GitFileModes::default()
+        } else {
+            GitFileModes::load(workspace.root())?
+        };
 
         Ok(Self {
-            manifest_path,
             options,
-            config,
+            workspace,
             package_graph,
+            workspace_metadata,
+            hash_algorithm,
+            lockfile,
+            hash_cache,
+            git_file_modes,
+            hashes: RefCell::default(),
         })
     }
 
@@ -138,15 +225,52 @@ impl Context {
         &self.options
     }
 
-    pub fn workspace(&self) -> Result<cargo::core::Workspace<'_>> {
-        cargo::core::Workspace::new(&self.manifest_path, &self.config)
-            .map_err(|err| Error::new("failed to load Cargo workspace").with_source(err))
+    pub(crate) fn hash_cache(&self) -> &HashCache {
+        &self.hash_cache
     }
 
-    pub fn target_root(&self) -> Result<PathBuf> {
-        let workspace = self.workspace()?;
+    pub(crate) fn git_file_modes(&self) -> &GitFileModes {
+        &self.git_file_modes
+    }
+
+    /// Save the file digests computed so far to the on-disk hash cache, so
+    /// that subsequent invocations can reuse them.
+    pub fn save_hash_cache(&self) -> Result<()> {
+        self.hash_cache.save()
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub(crate) fn workspace_metadata(&self) -> &WorkspaceMetadata {
+        &self.workspace_metadata
+    }
 
-        Ok(workspace.target_dir().into_path_unlocked())
+    /// Get the options used to digest the files of the packages with the
+    /// specified algorithm.
+    pub(crate) fn digest_options(&self, algorithm: HashAlgorithm) -> DigestOptions {
+        DigestOptions {
+            algorithm,
+            normalize_line_endings: self.workspace_metadata.normalize_line_endings,
+        }
+    }
+
+    /// Get the format of the hashes computed in this context.
+    pub fn hash_format(&self) -> HashFormat {
+        HashFormat::current(self.hash_algorithm)
+    }
+
+    pub fn workspace(&self) -> &cargo::core::Workspace<'_> {
+        &self.workspace
+    }
+
+    pub fn workspace_root(&self) -> &Path {
+        self.package_graph.workspace().root().as_std_path()
+    }
+
+    pub fn target_root(&self) -> Result<PathBuf> {
+        Ok(self.workspace.target_dir().into_path_unlocked())
     }
 
     pub fn packages(&self) -> Result<Vec<Package<'_>>> {
@@ -196,68 +320,334 @@ impl Context {
             .collect()
     }
 
-    pub fn resolve_changed_packages(&self, start: &str) -> Result<Vec<Package<'_>>> {
-        let changed_files = self.get_changed_files(start)?;
-
+    /// Resolve the packages whose sources are affected by the specified Git
+    /// changes, along with all the packages that depend on them, directly or
+    /// not.
+    ///
+    /// Each package appears once and packages are returned in dependency
+    /// order, so that any package comes after the packages it depends on.
+    pub fn resolve_changed_packages(&self, changes: &GitChanges) -> Result<Vec<Package<'_>>> {
         Ok(self
-            .packages()?
+            .explain_changed_packages(changes)?
             .into_iter()
-            .filter_map(|p| {
-                for changed_file in &changed_files {
-                    if p.sources().contains(changed_file) {
-                        return Some(
-                            p.dependant_packages()
-                                .map(|packages| std::iter::once(p).chain(packages)),
-                        );
-                    }
+            .map(|(package, _)| package)
+            .collect())
+    }
+
+    /// Resolve the changed packages like `resolve_changed_packages`, along
+    /// with the reason why each package is considered changed.
+    pub fn explain_changed_packages(
+        &self,
+        changes: &GitChanges,
+    ) -> Result<Vec<(Package<'_>, ChangeExplanation)>> {
+        let repo = self.git_repository()?;
+        let changed_files = changes.changed_files(&repo)?;
+        let packages = self.packages()?;
+
+        let mut direct_changes: BTreeMap<&str, (&guppy::PackageId, Vec<DirectChange>)> =
+            BTreeMap::new();
+
+        for package in &packages {
+            let files: Vec<_> = changed_files
+                .iter()
+                .filter_map(|changed_file| package.sources().key(changed_file))
+                .collect();
+
+            if !files.is_empty() {
+                direct_changes
+                    .entry(package.name())
+                    .or_insert_with(|| (package.id(), vec![]))
+                    .1
+                    .push(DirectChange::Files { files });
+            }
+        }
+
+        // Manifests and the lockfile are not part of the sources: changes to
+        // them are attributed to the packages they actually affect.
+        for (package, change) in manifest_changes::changed_packages(
+            &repo,
+            changes,
+            &changed_files,
+            self.workspace_root(),
+            &packages,
+        )? {
+            direct_changes
+                .entry(package.name())
+                .or_insert_with(|| (package.id(), vec![]))
+                .1
+                .push(change);
+        }
+
+        for (package, change) in Triggers::new(&self.workspace_metadata.triggers)?
+            .changed_packages(self.workspace_root(), &changed_files, &packages)?
+        {
+            direct_changes
+                .entry(package.name())
+                .or_insert_with(|| (package.id(), vec![]))
+                .1
+                .push(change);
+        }
+
+        let mut explanations = HashMap::new();
+        let mut queue = VecDeque::new();
+
+        // Packages that changed directly are visited first - by name, for
+        // determinism - and the walk is breadth-first, so that each dependant
+        // package is reached through its shortest dependency path.
+        for (name, (id, changes)) in direct_changes {
+            explanations.insert(
+                id.clone(),
+                ChangeExplanation {
+                    package: name.to_string(),
+                    dependency_path: vec![],
+                    changes,
+                },
+            );
+            queue.push_back(id.clone());
+        }
+
+        while let Some(id) = queue.pop_front() {
+            let package_metadata = self.package_metadata(&id)?;
+            let explanation = explanations[&id].clone();
+
+            for link in package_metadata.reverse_direct_links() {
+                let dependant = link.from();
+
+                if !dependant.in_workspace() || explanations.contains_key(dependant.id()) {
+                    continue;
                 }
 
-                None
-            })
-            .collect::<Result<Vec<_>>>()?
+                explanations.insert(
+                    dependant.id().clone(),
+                    ChangeExplanation {
+                        package: dependant.name().to_string(),
+                        dependency_path: std::iter::once(explanation.package.clone())
+                            .chain(explanation.dependency_path.iter().cloned())
+                            .collect(),
+                        changes: explanation.changes.clone(),
+                    },
+                );
+                queue.push_back(dependant.id().clone());
+            }
+        }
+
+        let ordered_ids = self.dependant_package_ids(explanations.keys())?;
+
+        let mut packages: HashMap<_, _> = packages
             .into_iter()
-            .flatten()
+            .map(|package| (package.id().clone(), package))
+            .collect();
+
+        Ok(ordered_ids
+            .into_iter()
+            .filter_map(|id| Some((packages.remove(id)?, explanations.remove(id)?)))
             .collect())
     }
 
-    fn git_repository(&self) -> Result<Repository> {
-        Repository::open(self.workspace()?.root())
-            .map_err(|err| Error::new("failed to open Git repository").with_source(err))
+    /// Resolve the packages whose hash does not match the tag of their current
+    /// version, in dependency order.
+    ///
+    /// Unlike Git-based change detection, this does not require any history:
+    /// the hashes are compared to the tags recorded in the manifests. As the
+    /// hash of a package covers the hashes of its dependencies, the packages
+    /// that depend on an untagged package are untagged as well.
+    pub fn resolve_untagged_packages(&self) -> Result<Vec<Package<'_>>> {
+        let packages = self.packages()?;
+
+        self.compute_hashes(&packages)?;
+
+        let mut untagged_packages = HashMap::new();
+
+        for package in packages {
+            if !package.tag_matches()? {
+                debug!("{} does not match its tag", package.name());
+
+                untagged_packages.insert(package.id().clone(), package);
+            }
+        }
+
+        Ok(self
+            .dependant_package_ids(untagged_packages.keys())?
+            .into_iter()
+            .filter_map(|id| untagged_packages.remove(id))
+            .collect())
     }
 
-    fn get_changed_files(&self, start: &str) -> Result<Vec<PathBuf>> {
-        let repo = self.git_repository()?;
-        let start = repo
-            .revparse_single(start)
-            .map_err(|err| Error::new("failed to parse Git revision").with_source(err))?
-            .as_commit()
-            .ok_or_else(|| Error::new("reference is not a commit"))?
-            .tree()
-            .unwrap();
+    /// Get the ids of the specified workspace packages and of all the
+    /// workspace packages that depend on them, transitively, in dependency
+    /// order.
+    pub(crate) fn dependant_package_ids<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a guppy::PackageId>,
+    ) -> Result<Vec<&guppy::PackageId>> {
+        Ok(self
+            .package_graph
+            .query_reverse(ids)
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve()
+            .packages(DependencyDirection::Reverse)
+            .filter(guppy::graph::PackageMetadata::in_workspace)
+            .map(|package_metadata| package_metadata.id())
+            .collect())
+    }
+
+    /// Get the ids of the specified workspace packages and of all the
+    /// workspace packages they depend on, transitively, in dependency order.
+    pub(crate) fn dependency_package_ids<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a guppy::PackageId>,
+    ) -> Result<Vec<&guppy::PackageId>> {
+        Ok(self
+            .package_graph
+            .query_forward(ids)
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve()
+            .packages(DependencyDirection::Reverse)
+            .filter(guppy::graph::PackageMetadata::in_workspace)
+            .map(|package_metadata| package_metadata.id())
+            .collect())
+    }
+
+    /// Get the metadata of all the workspace packages, without loading them.
+    pub(crate) fn workspace_packages_metadata(
+        &self,
+    ) -> impl Iterator<Item = guppy::graph::PackageMetadata<'_>> {
+        self.package_graph.workspace().iter()
+    }
+
+    /// Get the metadata of the workspace package with the specified id.
+    pub(crate) fn package_metadata(
+        &self,
+        id: &guppy::PackageId,
+    ) -> Result<guppy::graph::PackageMetadata<'_>> {
+        self.package_graph
+            .metadata(id)
+            .map_err(|err| Error::new("failed to resolve package").with_source(err))
+    }
 
-        let diff = repo
-            .diff_tree_to_workdir(Some(&start), None)
-            .map_err(|err| Error::new("failed to generate diff").with_source(err))?;
+    /// Get the hash of the specified package.
+    ///
+    /// Hashes are computed at most once per context: subsequent calls for the
+    /// same package - or for any package that depends on it - reuse the cached
+    /// value.
+    pub fn package_hash(&self, package: &Package<'_>) -> Result<String> {
+        let key = (package.id().clone(), package.hash_algorithm());
+
+        if let Some(hash) = self.hashes.borrow().get(&key) {
+            return Ok(hash.clone());
+        }
 
-        let prefix = repo
-            .path()
-            .parent()
-            .ok_or_else(|| Error::new("failed to determine Git repository path"))?;
+        let hash = HashSource::new(package)?.hash();
 
-        let mut result = Vec::new();
+        self.hashes.borrow_mut().insert(key, hash.clone());
 
-        diff.print(git2::DiffFormat::NameOnly, |_, _, l| {
-            let path = prefix.join(PathBuf::from(
-                std::str::from_utf8(l.content()).unwrap().trim_end(),
-            ));
+        Ok(hash)
+    }
 
-            result.push(path);
+    /// Get the hash of the workspace package with the specified id, computed
+    /// with the specified algorithm.
+    pub(crate) fn package_hash_by_id(
+        &self,
+        id: &guppy::PackageId,
+        algorithm: HashAlgorithm,
+    ) -> Result<String> {
+        if let Some(hash) = self.hashes.borrow().get(&(id.clone(), algorithm)) {
+            return Ok(hash.clone());
+        }
 
-            true
-        })
-        .map_err(|err| Error::new("failed to print diff").with_source(err))?;
+        Package::with_hash_algorithm(self, self.package_metadata(id)?, algorithm)?.hash()
+    }
 
-        Ok(result)
+    /// Compute the hashes of the specified packages and of all the workspace
+    /// packages they depend on.
+    ///
+    /// The package graph is walked once, dependencies first, so that every
+    /// package is hashed exactly once and can rely on the cached hashes of its
+    /// dependencies.
+    pub fn compute_hashes(&self, packages: &[Package<'_>]) -> Result<()> {
+        let package_set = self
+            .package_graph
+            .query_forward(packages.iter().map(Package::id))
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve();
+
+        for package_metadata in package_set.packages(DependencyDirection::Reverse) {
+            if !package_metadata.in_workspace()
+                || self
+                    .hashes
+                    .borrow()
+                    .contains_key(&(package_metadata.id().clone(), self.hash_algorithm))
+            {
+                continue;
+            }
+
+            match packages.iter().find(|p| p.id() == package_metadata.id()) {
+                Some(package) => self.package_hash(package)?,
+                None => Package::new(self, package_metadata)?.hash()?,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Get the resolved external - that is non-workspace - dependencies of the
+    /// specified package, transitively.
+    ///
+    /// The result is keyed by package id and contains the Cargo.lock checksum
+    /// of each package, when it has one.
+    pub(crate) fn external_dependencies(
+        &self,
+        id: &guppy::PackageId,
+    ) -> Result<BTreeMap<String, Option<String>>> {
+        Ok(self
+            .package_graph
+            .query_forward([id])
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve()
+            .packages(DependencyDirection::Forward)
+            .filter(|package_metadata| !package_metadata.in_workspace())
+            .map(|package_metadata| {
+                let checksum = self.lockfile.checksum(
+                    package_metadata.name(),
+                    &package_metadata.version().to_string(),
+                    package_metadata.source().external_source(),
+                );
+
+                (
+                    package_metadata.id().repr().to_string(),
+                    checksum.map(ToString::to_string),
+                )
+            })
+            .collect())
+    }
+
+    /// Get the names and root directories of the path dependencies of the
+    /// specified package - direct or not - that are not part of the workspace.
+    pub(crate) fn non_workspace_path_dependencies(
+        &self,
+        id: &guppy::PackageId,
+    ) -> Result<Vec<(&str, &Path)>> {
+        Ok(self
+            .package_graph
+            .query_forward([id])
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve()
+            .packages(DependencyDirection::Forward)
+            .filter(|package_metadata| {
+                !package_metadata.in_workspace() && package_metadata.source().is_path()
+            })
+            .filter_map(|package_metadata| {
+                package_metadata
+                    .manifest_path()
+                    .parent()
+                    .map(|root| (package_metadata.name(), root.as_std_path()))
+            })
+            .collect())
+    }
+
+    fn git_repository(&self) -> Result<Repository> {
+        Repository::open(self.workspace.root())
+            .map_err(|err| Error::new("failed to open Git repository").with_source(err))
     }
 
     ///// Build all the collected distribution targets.
@@ -328,3 +718,126 @@ impl Context {
     //    Ok(())
     //}
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_workspace::TestWorkspace, GitChanges, GitRange};
+
+    #[test]
+    fn test_compute_hashes() {
+        let workspace = TestWorkspace::new("compute-hashes");
+        workspace
+            .add_package("core", &[], "")
+            .add_package("api", &["core"], "")
+            .add_package("service", &["api"], "");
+
+        let hashes = |context: &super::Context| {
+            context
+                .packages()
+                .unwrap()
+                .iter()
+                .map(|package| (package.name().to_string(), package.hash().unwrap()))
+                .collect::<std::collections::BTreeMap<_, _>>()
+        };
+
+        let context = workspace.context();
+        let packages = context.packages().unwrap();
+        let service = packages
+            .iter()
+            .find(|package| package.name() == "service")
+            .unwrap();
+
+        // Hashing a package hashes all the packages it depends on, once.
+        context
+            .compute_hashes(std::slice::from_ref(service))
+            .unwrap();
+        assert_eq!(context.hashes.borrow().len(), 3);
+
+        let before = hashes(&context);
+        assert_eq!(context.hashes.borrow().len(), 3);
+
+        // Hashes are not computed again within a context.
+        workspace.write("packages/core/src/lib.rs", "pub fn core() {}");
+        assert_eq!(hashes(&context), before);
+
+        // The hashes of the dependents reflect the changes of their
+        // dependencies.
+        let after = hashes(&workspace.context());
+        for name in ["core", "api", "service"] {
+            assert_ne!(after[name], before[name], "{name}");
+        }
+
+        // Hashing a package on its own gives the same hash as hashing it
+        // along with its dependencies.
+        let context = workspace.context();
+        let service = context
+            .packages()
+            .unwrap()
+            .into_iter()
+            .find(|package| package.name() == "service")
+            .unwrap();
+        assert_eq!(service.hash().unwrap(), after["service"]);
+    }
+
+    #[test]
+    fn test_resolve_changed_packages() {
+        let workspace = TestWorkspace::new("changed-packages");
+        workspace
+            .add_package("core", &[], "")
+            .add_package("api", &["core"], "")
+            .add_package("service", &["api"], "")
+            .add_package("tool", &["core"], "")
+            .add_package("web", &[], "");
+        // Resolving the workspace writes its lockfile.
+        workspace.context();
+        workspace.commit("Initial commit");
+
+        let changed = |workspace: &TestWorkspace| {
+            workspace
+                .context()
+                .explain_changed_packages(&GitChanges::new(GitRange::Since("HEAD".to_string())))
+                .unwrap()
+                .into_iter()
+                .map(|(package, explanation)| {
+                    assert_eq!(package.name(), explanation.package);
+
+                    (explanation.package, explanation.dependency_path)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(changed(&workspace), []);
+
+        // Dependants are changed transitively, after their dependencies.
+        workspace.write("packages/api/src/lib.rs", "pub fn api() {}");
+        assert_eq!(
+            changed(&workspace),
+            [
+                ("api".to_string(), vec![]),
+                ("service".to_string(), vec!["api".to_string()]),
+            ]
+        );
+
+        workspace.write("packages/core/src/lib.rs", "pub fn core() {}");
+        let changes = changed(&workspace);
+        let position = |name: &str| changes.iter().position(|(package, _)| package == name);
+
+        assert_eq!(changes.len(), 4);
+        assert_eq!(position("core"), Some(0));
+        assert!(position("api") < position("service"));
+        assert!(position("tool").is_some());
+        assert!(position("web").is_none());
+
+        // Packages that changed directly are reported as such, even when one
+        // of their dependencies changed too.
+        assert_eq!(changes[position("api").unwrap()].1, Vec::<String>::new());
+        assert_eq!(
+            changes[position("service").unwrap()].1,
+            vec!["api".to_string()]
+        );
+        assert_eq!(
+            changes[position("tool").unwrap()].1,
+            vec!["core".to_string()]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Parsing of the dep-info files Cargo writes next to the build artifacts.
+//!
+//! These Makefile-like files list all the files a build artifact was built
+//! from, including the files pulled in with `include_bytes!` or `include_str!`.
+
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::{Error, Result};
+
+/// Read the dependencies listed in the specified dep-info file.
+///
+/// A missing file - as the package was never built - results in an empty list.
+pub(crate) fn read_dep_info(path: &Path) -> Result<Vec<PathBuf>> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => Ok(parse_dep_info(&data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            debug!("No dep-info file at `{}`", path.display());
+
+            Ok(vec![])
+        }
+        Err(err) => Err(Error::new("failed to read dep-info file")
+            .with_source(err)
+            .with_output(format!("Path: {}", path.display()))),
+    }
+}
+
+/// Parse the dependencies listed in a dep-info file.
+///
+/// Each line is written `<target>: <dependency> <dependency>...`, with spaces
+/// in paths escaped by a backslash.
+fn parse_dep_info(data: &str) -> Vec<PathBuf> {
+    let mut dependencies = Vec::new();
+
+    for line in data.lines() {
+        // Looking for `: ` rather than `:` keeps Windows drive letters intact.
+        let Some((_, dependencies_list)) = line.split_once(": ") else {
+            continue;
+        };
+
+        let mut dependency = String::new();
+        let mut chars = dependencies_list.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&' ') => {
+                    dependency.push(' ');
+                    chars.next();
+                }
+                ' ' => {
+                    if !dependency.is_empty() {
+                        dependencies.push(PathBuf::from(std::mem::take(&mut dependency)));
+                    }
+                }
+                c => dependency.push(c),
+            }
+        }
+
+        if !dependency.is_empty() {
+            dependencies.push(PathBuf::from(dependency));
+        }
+    }
+
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dep_info() {
+        assert_eq!(
+            parse_dep_info(
+                "/repo/target/debug/mybinary: /repo/mybinary/src/main.rs /repo/assets/my\\ logo.png\n\n/repo/mybinary/src/main.rs:\n"
+            ),
+            vec![
+                PathBuf::from("/repo/mybinary/src/main.rs"),
+                PathBuf::from("/repo/assets/my logo.png"),
+            ]
+        );
+
+        assert_eq!(
+            parse_dep_info(
+                "C:\\repo\\target\\debug\\mybinary.exe: C:\\repo\\mybinary\\src\\main.rs"
+            ),
+            vec![PathBuf::from("C:\\repo\\mybinary\\src\\main.rs")]
+        );
+    }
+}
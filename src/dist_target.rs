@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{aws_lambda::AwsLambdaDistTarget, docker::DockerDistTarget, Result};
+use crate::{aws_lambda::AwsLambdaDistTarget, docker::DockerDistTarget, reporter::Subject, Result};
 
 // Quite frankly, this structure is not used much and never in a context where
 // its performance is critical. So we don't really care about the size of the
@@ -12,6 +12,41 @@ pub(crate) enum DistTarget<'g> {
 }
 
 impl DistTarget<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            DistTarget::AwsLambda(dist_target) => &dist_target.name,
+            DistTarget::Docker(dist_target) => &dist_target.name,
+        }
+    }
+
+    /// Get the type of the distribution target, as written in the metadata.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DistTarget::AwsLambda(_) => "aws-lambda",
+            DistTarget::Docker(_) => "docker",
+        }
+    }
+
+    /// Get the subject of the operations on the distribution target, as
+    /// reported.
+    pub fn subject(&self) -> Subject {
+        let package = match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.package,
+            DistTarget::Docker(dist_target) => dist_target.package,
+        };
+
+        Subject::dist_target(package, self.name(), self.type_name())
+    }
+
+    /// Get where the distribution target is published: the name of a Docker
+    /// image or the URL of an S3 object.
+    pub fn destination(&self) -> Result<String> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.destination(),
+            DistTarget::Docker(dist_target) => dist_target.destination(),
+        }
+    }
+
     pub fn build(&self) -> Result<()> {
         match self {
             DistTarget::AwsLambda(dist_target) => dist_target.build(),
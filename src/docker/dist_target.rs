@@ -14,8 +14,11 @@ use log::{debug, warn};
 use regex::Regex;
 
 use crate::{
-    action_step, ignore_step, rust::is_current_target_runtime, Context, Error, ErrorContext,
-    Package, Result,
+    ignore_step,
+    reporter::{self, Subject},
+    run_log,
+    rust::is_current_target_runtime,
+    Context, Error, ErrorContext, Package, Result,
 };
 
 use super::DockerMetadata;
@@ -54,6 +57,8 @@ impl<'g> DockerDistTarget<'g> {
 
         self.build_dockerfile(&dockerfile)?;
 
+        reporter::artifact(self.subject(), self.docker_image_name()?);
+
         Ok(())
     }
 
@@ -86,22 +91,27 @@ impl<'g> DockerDistTarget<'g> {
 
         let args = vec!["pull", docker_image_name];
 
-        action_step!("Running", "`docker {}`", args.join(" "),);
+        reporter::command(format!("docker {}", args.join(" ")));
 
         cmd.args(args);
 
         if self.context().options().verbose {
-            let status = cmd.status().map_err(Error::from_source).with_full_context(
-                "failed to pull Docker image",
-                "The pull of the Docker image failed which could indicate a configuration problem.",
-            )?;
+            let status = run_log::run_command(&mut cmd, true)
+                .map(|output| output.status)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to pull Docker image",
+                    "The pull of the Docker image failed which could indicate a configuration problem.",
+                )?;
 
             Ok(status.success())
         } else {
-            let output = cmd.output().map_err(Error::from_source).with_full_context(
-                "failed to pull Docker image",
-                "The pull of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
-            )?;
+            let output = run_log::run_command(&mut cmd, false)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to pull Docker image",
+                    "The pull of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
+                )?;
 
             Ok(output.status.success())
         }
@@ -158,15 +168,18 @@ impl<'g> DockerDistTarget<'g> {
             return Ok(());
         }
 
-        action_step!("Running", "`docker {}`", args.join(" "),);
+        reporter::command(format!("docker {}", args.join(" ")));
 
         cmd.args(args);
 
         if self.context().options().verbose {
-            let status = cmd.status().map_err(Error::from_source).with_full_context(
-                "failed to push Docker image",
-                "The push of the Docker image failed which could indicate a configuration problem.",
-            )?;
+            let status = run_log::run_command(&mut cmd, true)
+                .map(|output| output.status)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to push Docker image",
+                    "The push of the Docker image failed which could indicate a configuration problem.",
+                )?;
 
             if !status.success() {
                 return Err(Error::new("failed to push Docker image").with_explanation(
@@ -174,10 +187,12 @@ impl<'g> DockerDistTarget<'g> {
                 ));
             }
         } else {
-            let output = cmd.output().map_err(Error::from_source).with_full_context(
-                "failed to push Docker image",
-                "The push of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
-            )?;
+            let output = run_log::run_command(&mut cmd, false)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to push Docker image",
+                    "The push of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
+                )?;
 
             if !output.status.success() {
                 return Err(Error::new("failed to push Docker image")
@@ -186,6 +201,8 @@ impl<'g> DockerDistTarget<'g> {
             };
         }
 
+        reporter::artifact(self.subject(), docker_image_name);
+
         Ok(())
     }
 
@@ -272,7 +289,7 @@ impl<'g> DockerDistTarget<'g> {
 
         let args = vec!["build", "-t", &docker_image_name, "."];
 
-        action_step!("Running", "`docker {}`", args.join(" "),);
+        reporter::command(format!("docker {}", args.join(" ")));
 
         cmd.args(args);
 
@@ -280,10 +297,13 @@ impl<'g> DockerDistTarget<'g> {
         cmd.env("DOCKER_SCAN_SUGGEST", "false");
 
         if self.context().options().verbose {
-            let status = cmd.status().map_err(Error::from_source).with_full_context(
-                "failed to build Docker image",
-                "The build of the Docker image failed which could indicate a configuration problem.",
-            )?;
+            let status = run_log::run_command(&mut cmd, true)
+                .map(|output| output.status)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to build Docker image",
+                    "The build of the Docker image failed which could indicate a configuration problem.",
+                )?;
 
             if !status.success() {
                 return Err(Error::new("failed to build Docker image").with_explanation(
@@ -291,10 +311,12 @@ impl<'g> DockerDistTarget<'g> {
                 ));
             }
         } else {
-            let output = cmd.output().map_err(Error::from_source).with_full_context(
-                "failed to build Docker image",
-                "The build of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
-            )?;
+            let output = run_log::run_command(&mut cmd, false)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to build Docker image",
+                    "The build of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
+                )?;
 
             if !output.status.success() {
                 return Err(Error::new("failed to build Docker image")
@@ -326,6 +348,15 @@ impl<'g> DockerDistTarget<'g> {
         }
     }
 
+    fn subject(&self) -> Subject {
+        Subject::dist_target(self.package, &self.name, "docker")
+    }
+
+    /// Get the name of the Docker image, as it is pushed.
+    pub fn destination(&self) -> Result<String> {
+        self.docker_image_name()
+    }
+
     fn docker_image_name(&self) -> Result<String> {
         Ok(format!(
             "{}/{}:{}",
@@ -365,7 +396,7 @@ impl<'g> DockerDistTarget<'g> {
     }
 
     fn build_binaries(&self) -> Result<HashMap<String, PathBuf>> {
-        let ws = self.context().workspace()?;
+        let ws = self.context().workspace();
         let mut compile_options = CompileOptions::new(ws.config(), CompileMode::Build).unwrap();
 
         compile_options.spec =
@@ -380,7 +411,7 @@ impl<'g> DockerDistTarget<'g> {
                 )];
         }
 
-        compile(&ws, &compile_options)
+        compile(ws, &compile_options)
             .map(|compilation| {
                 compilation
                     .binaries
@@ -0,0 +1,304 @@
+//! Detection of the files changed in a Git repository.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use git2::{DiffOptions, Repository};
+use log::debug;
+
+use crate::{Error, Result};
+
+/// The branch used to compute the merge-base when none is specified.
+pub const DEFAULT_MERGE_BASE_BRANCH: &str = "origin/main";
+
+/// A range of Git revisions to detect changes in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitRange {
+    /// The changes since the specified revision, up to the working directory.
+    Since(String),
+    /// `A..B`: the changes from `A` to `B`.
+    Between(String, String),
+    /// `A...B`: the changes from the merge-base of `A` and `B` to `B`.
+    ///
+    /// This is what a pull request from `B` into `A` contains.
+    MergeBase(String, String),
+}
+
+impl FromStr for GitRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Like Git, an omitted side of a range defaults to `HEAD`.
+        let revision = |s: &str| {
+            if s.is_empty() {
+                "HEAD".to_string()
+            } else {
+                s.to_string()
+            }
+        };
+
+        let range = if let Some((start, end)) = s.split_once("...") {
+            Self::MergeBase(revision(start), revision(end))
+        } else if let Some((start, end)) = s.split_once("..") {
+            Self::Between(revision(start), revision(end))
+        } else {
+            Self::Since(s.to_string())
+        };
+
+        let is_valid = match &range {
+            Self::Since(start) => !start.is_empty(),
+            Self::Between(start, end) | Self::MergeBase(start, end) => {
+                !start.contains("..") && !end.contains("..")
+            }
+        };
+
+        if is_valid {
+            Ok(range)
+        } else {
+            Err(Error::new("invalid Git range").with_explanation(format!(
+                "`{s}` is not a valid Git range. Specify a revision, `A..B` or `A...B`."
+            )))
+        }
+    }
+}
+
+impl Display for GitRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Since(start) => write!(f, "{start}"),
+            Self::Between(start, end) => write!(f, "{start}..{end}"),
+            Self::MergeBase(start, end) => write!(f, "{start}...{end}"),
+        }
+    }
+}
+
+/// The set of changes used to detect changed packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitChanges {
+    pub range: GitRange,
+    /// Whether untracked files count as changes.
+    ///
+    /// Only applies to changes up to the working directory.
+    pub include_untracked: bool,
+    /// Whether to only consider the changes staged in the index, ignoring the
+    /// working directory.
+    ///
+    /// Only applies to changes since a single revision.
+    pub staged_only: bool,
+}
+
+impl GitChanges {
+    pub fn new(range: GitRange) -> Self {
+        Self {
+            range,
+            include_untracked: false,
+            staged_only: false,
+        }
+    }
+
+    /// The changes since the merge-base of the specified branch and `HEAD`.
+    pub fn since_merge_base(branch: &str) -> Self {
+        Self::new(GitRange::MergeBase(branch.to_string(), "HEAD".to_string()))
+    }
+
+    /// Get the absolute paths of the files changed in the specified
+    /// repository.
+    ///
+    /// Both the old and new paths of renamed files are returned.
+    pub(crate) fn changed_files(&self, repo: &Repository) -> Result<Vec<PathBuf>> {
+        if !matches!(self.range, GitRange::Since(_)) && (self.include_untracked || self.staged_only)
+        {
+            return Err(Error::new("invalid Git changes").with_explanation(format!(
+                "Untracked and staged files can only be considered for changes since a single revision, but `{}` is a range.",
+                self.range
+            )));
+        }
+
+        let mut options = DiffOptions::new();
+
+        if self.include_untracked {
+            options.include_untracked(true).recurse_untracked_dirs(true);
+        }
+
+        let start = self.start_tree(repo)?;
+
+        let diff = match &self.range {
+            GitRange::Since(_) if self.staged_only => {
+                repo.diff_tree_to_index(Some(&start), None, Some(&mut options))
+            }
+            GitRange::Since(_) => repo.diff_tree_to_workdir(Some(&start), Some(&mut options)),
+            GitRange::Between(_, end) | GitRange::MergeBase(_, end) => repo.diff_tree_to_tree(
+                Some(&start),
+                Some(&resolve_tree(repo, end)?),
+                Some(&mut options),
+            ),
+        }
+        .map_err(|err| Error::new("failed to generate diff").with_source(err))?;
+
+        let prefix = repo
+            .workdir()
+            .ok_or_else(|| Error::new("failed to determine Git repository path"))?;
+
+        let changed_files: BTreeSet<_> = diff
+            .deltas()
+            .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+            .flatten()
+            .map(|path| prefix.join(path))
+            .collect();
+
+        debug!("{} files changed in `{}`", changed_files.len(), self.range);
+
+        Ok(changed_files.into_iter().collect())
+    }
+
+    /// Read the content of the specified file before and after the changes.
+    ///
+    /// A file that does not exist on one side is read as `None`.
+    pub(crate) fn file_versions(
+        &self,
+        repo: &Repository,
+        path: &Path,
+    ) -> Result<(Option<String>, Option<String>)> {
+        let relative_path = repo
+            .workdir()
+            .and_then(|workdir| path.strip_prefix(workdir).ok())
+            .ok_or_else(|| {
+                Error::new("file is outside of the Git repository")
+                    .with_output(format!("Path: {}", path.display()))
+            })?;
+
+        let old = read_tree_file(repo, &self.start_tree(repo)?, relative_path)?;
+
+        let new = match &self.range {
+            GitRange::Since(_) if self.staged_only => {
+                let index = repo
+                    .index()
+                    .map_err(|err| Error::new("failed to read Git index").with_source(err))?;
+
+                match index.get_path(relative_path, 0) {
+                    Some(entry) => Some(read_blob(repo, entry.id)?),
+                    None => None,
+                }
+            }
+            GitRange::Since(_) => match std::fs::read(path) {
+                Ok(data) => Some(String::from_utf8_lossy(&data).into_owned()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => {
+                    return Err(Error::new("failed to read file")
+                        .with_source(err)
+                        .with_output(format!("Path: {}", path.display())))
+                }
+            },
+            GitRange::Between(_, end) | GitRange::MergeBase(_, end) => {
+                read_tree_file(repo, &resolve_tree(repo, end)?, relative_path)?
+            }
+        };
+
+        Ok((old, new))
+    }
+
+    /// Get the tree the changes start from.
+    fn start_tree<'r>(&self, repo: &'r Repository) -> Result<git2::Tree<'r>> {
+        match &self.range {
+            GitRange::Since(start) | GitRange::Between(start, _) => resolve_tree(repo, start),
+            GitRange::MergeBase(start, end) => {
+                let merge_base = repo
+                    .merge_base(resolve_commit(repo, start)?.id(), resolve_commit(repo, end)?.id())
+                    .map_err(|err| {
+                        Error::new("failed to determine merge-base")
+                            .with_source(err)
+                            .with_explanation(format!(
+                                "`{start}` and `{end}` may not have a common ancestor. If running in a shallow clone, you may need to fetch more history."
+                            ))
+                    })?;
+
+                debug!("Merge-base of `{}` and `{}` is {}", start, end, merge_base);
+
+                repo.find_commit(merge_base)
+                    .and_then(|commit| commit.tree())
+                    .map_err(|err| Error::new("failed to read merge-base").with_source(err))
+            }
+        }
+    }
+}
+
+fn resolve_commit<'r>(repo: &'r Repository, revision: &str) -> Result<git2::Commit<'r>> {
+    repo.revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|err| {
+            Error::new("failed to parse Git revision")
+                .with_source(err)
+                .with_output(format!("Revision: {revision}"))
+        })
+}
+
+fn read_tree_file(repo: &Repository, tree: &git2::Tree<'_>, path: &Path) -> Result<Option<String>> {
+    match tree.get_path(path) {
+        Ok(entry) => read_blob(repo, entry.id()).map(Some),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(Error::new("failed to read Git tree").with_source(err)),
+    }
+}
+
+fn read_blob(repo: &Repository, id: git2::Oid) -> Result<String> {
+    repo.find_blob(id)
+        .map(|blob| String::from_utf8_lossy(blob.content()).into_owned())
+        .map_err(|err| Error::new("failed to read Git blob").with_source(err))
+}
+
+fn resolve_tree<'r>(repo: &'r Repository, revision: &str) -> Result<git2::Tree<'r>> {
+    resolve_commit(repo, revision)?
+        .tree()
+        .map_err(|err| Error::new("failed to read Git tree").with_source(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_range_parse() {
+        let since = |start: &str| GitRange::Since(start.to_string());
+        let between =
+            |start: &str, end: &str| GitRange::Between(start.to_string(), end.to_string());
+        let merge_base =
+            |start: &str, end: &str| GitRange::MergeBase(start.to_string(), end.to_string());
+
+        assert_eq!("main".parse::<GitRange>().unwrap(), since("main"));
+        assert_eq!(
+            "v1.0.0..feature".parse::<GitRange>().unwrap(),
+            between("v1.0.0", "feature")
+        );
+        assert_eq!(
+            "origin/main...HEAD".parse::<GitRange>().unwrap(),
+            merge_base("origin/main", "HEAD")
+        );
+        assert_eq!(
+            "origin/main...".parse::<GitRange>().unwrap(),
+            merge_base("origin/main", "HEAD")
+        );
+        assert_eq!(
+            "main..".parse::<GitRange>().unwrap(),
+            between("main", "HEAD")
+        );
+        assert_eq!(
+            "..main".parse::<GitRange>().unwrap(),
+            between("HEAD", "main")
+        );
+
+        assert!("".parse::<GitRange>().is_err());
+        assert!("a..b..c".parse::<GitRange>().is_err());
+        assert!("a...b...c".parse::<GitRange>().is_err());
+    }
+
+    #[test]
+    fn test_git_range_display() {
+        for range in ["main", "a..b", "a...b"] {
+            assert_eq!(range.parse::<GitRange>().unwrap().to_string(), range);
+        }
+    }
+}
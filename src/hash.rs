@@ -1,15 +1,169 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt::Display, io::Write, str::FromStr};
 
 use cargo_metadata::camino::Utf8Path;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-use crate::{metadata::DistTargetMetadata, sources::Sources, Package, Result};
+use crate::{
+    build_config::BuildConfig, hash_manifest::HashManifest, metadata::DistTargetMetadata,
+    sources::SourceFile, Error, Package, Result,
+};
+
+/// The version of the hash format.
+///
+/// It must be incremented whenever the inputs of the hash change in a way that
+/// modifies the hash of an otherwise unchanged package, so that tags recorded
+/// with a previous format can be told apart from actual changes.
+///
+/// Version 1 hashes are prefixed with the algorithm only (`sha256:`) and were
+/// computed over absolute source paths.
+pub(crate) const HASH_FORMAT_VERSION: u32 = 7;
+
+/// An algorithm used to compute hashes and file digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[derive(Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+            Self::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(Error::new("unknown hash algorithm").with_explanation(format!(
+                "`{s}` is not a supported hash algorithm. Supported algorithms are `sha256` and `blake3`."
+            ))),
+        }
+    }
+}
+
+/// A hasher for one of the supported algorithms.
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    /// Get the hexadecimal representation of the hash.
+    pub(crate) fn finalize(self) -> String {
+        match self {
+            Self::Sha256(state) => format!("{:x}", state.finalize()),
+            Self::Blake3(state) => state.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Sha256(state) => state.write(buf),
+            Self::Blake3(state) => state.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The format of a hash: the algorithm used to compute it and the version of
+/// the hash inputs.
+///
+/// It is used as the prefix of hashes, like in `blake3-v5:...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashFormat {
+    pub algorithm: HashAlgorithm,
+    pub version: u32,
+}
+
+impl HashFormat {
+    /// Get the current hash format for the specified algorithm.
+    pub fn current(algorithm: HashAlgorithm) -> Self {
+        Self {
+            algorithm,
+            version: HASH_FORMAT_VERSION,
+        }
+    }
+
+    /// Parse the format of the specified hash.
+    ///
+    /// Returns `None` if the hash is not a valid hash.
+    pub fn parse(hash: &str) -> Option<Self> {
+        let (prefix, _) = hash.split_once(':')?;
+
+        match prefix.split_once("-v") {
+            Some((algorithm, version)) => Some(Self {
+                algorithm: algorithm.parse().ok()?,
+                version: version.parse().ok()?,
+            }),
+            // Version 1 hashes only ever used SHA-256.
+            None if prefix == "sha256" => Some(Self {
+                algorithm: HashAlgorithm::Sha256,
+                version: 1,
+            }),
+            None => None,
+        }
+    }
+
+    /// Whether hashes of this format were computed over different inputs than
+    /// the current ones, in which case they can never be compared to hashes
+    /// computed today.
+    pub fn is_outdated(&self) -> bool {
+        self.version < HASH_FORMAT_VERSION
+    }
+}
+
+impl Display for HashFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-v{}", self.algorithm, self.version)
+    }
+}
 
 /// A structure whose sole purpose is to help compute a deterministic hash of a
 /// given package.
 #[derive(Serialize)]
 pub(crate) struct HashSource<'g> {
+    #[serde(skip)]
+    algorithm: HashAlgorithm,
+    #[serde(flatten)]
+    fields: HashFields<'g>,
+    direct_links: Vec<String>,
+    #[serde(skip)]
+    direct_link_names: Vec<&'g str>,
+    external_dependencies: BTreeMap<String, Option<String>>,
+    sources: &'g BTreeMap<String, SourceFile>,
+    dist_targets: &'g BTreeMap<String, DistTargetMetadata>,
+    build_config: &'g BuildConfig,
+}
+
+/// The package metadata fields that take part in the hash.
+#[derive(Serialize)]
+struct HashFields<'g> {
+    format_version: u32,
     name: &'g str,
     version: &'g semver::Version,
     authors: &'g [String],
@@ -22,14 +176,11 @@ pub(crate) struct HashSource<'g> {
     repository: Option<&'g str>,
     edition: &'g str,
     links: Option<&'g str>,
-    direct_links: Vec<String>,
-    sources: &'g Sources,
-    dist_targets: &'g BTreeMap<String, DistTargetMetadata>,
 }
 
 impl<'g> HashSource<'g> {
     pub(crate) fn new(package: &'g Package<'g>) -> Result<Self> {
-        let direct_links = package
+        let (direct_link_names, direct_links): (Vec<_>, Vec<_>) = package
             .package_metadata()
             .direct_links()
             .map(|link| {
@@ -41,39 +192,155 @@ impl<'g> HashSource<'g> {
                 if link_package.in_workspace() {
                     package
                         .context()
-                        .resolve_package_by_name(link_package.name())?
-                        .hash()
+                        .package_hash_by_id(link_package.id(), package.hash_algorithm())
+                        .map(|hash| (link.dep_name(), hash))
                 } else {
-                    Ok(link_package.id().to_string())
+                    Ok((link.dep_name(), link_package.id().to_string()))
                 }
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        // Direct links only cover the direct dependencies: to detect changes
+        // deeper in the resolved dependency tree - like a lockfile update - we
+        // also depend on the full closure of external dependencies.
+        let external_dependencies = package.context().external_dependencies(package.id())?;
 
         Ok(Self {
-            name: package.package_metadata().name(),
-            version: package.package_metadata().version(),
-            authors: package.package_metadata().authors(),
-            description: package.package_metadata().description(),
-            license: package.package_metadata().license(),
-            license_file: package.package_metadata().license_file(),
-            categories: package.package_metadata().categories(),
-            keywords: package.package_metadata().keywords(),
-            readme: package.package_metadata().readme(),
-            repository: package.package_metadata().repository(),
-            edition: package.package_metadata().edition(),
-            links: package.package_metadata().links(),
+            algorithm: package.hash_algorithm(),
+            fields: HashFields {
+                format_version: HASH_FORMAT_VERSION,
+                name: package.package_metadata().name(),
+                version: package.package_metadata().version(),
+                authors: package.package_metadata().authors(),
+                description: package.package_metadata().description(),
+                license: package.package_metadata().license(),
+                license_file: package.package_metadata().license_file(),
+                categories: package.package_metadata().categories(),
+                keywords: package.package_metadata().keywords(),
+                readme: package.package_metadata().readme(),
+                repository: package.package_metadata().repository(),
+                edition: package.package_metadata().edition(),
+                links: package.package_metadata().links(),
+            },
             direct_links,
-            sources: package.sources(),
+            direct_link_names,
+            external_dependencies,
+            sources: package.sources().files(),
             dist_targets: &package.monorepo_metadata().dist_targets,
+            build_config: package.build_config(),
         })
     }
 
     pub(crate) fn hash(&self) -> String {
-        let mut state = Sha256::new();
+        hash_serializable(self, self.algorithm)
+    }
+
+    /// Compute a hash of the dependencies of the package only.
+    ///
+    /// As the hashes of workspace dependencies and the resolved external
+    /// dependencies are part of it, this hash covers the whole dependency
+    /// closure of the package.
+    pub(crate) fn dependencies_hash(&self) -> String {
+        #[derive(Serialize)]
+        struct DependenciesHashSource<'a> {
+            format_version: u32,
+            direct_links: &'a [String],
+            external_dependencies: &'a BTreeMap<String, Option<String>>,
+        }
 
-        // There is no reason for this write to ever fail so unwrap is fine.
-        serde_json::to_writer(&mut state, &self).unwrap();
+        hash_serializable(
+            &DependenciesHashSource {
+                format_version: self.fields.format_version,
+                direct_links: &self.direct_links,
+                external_dependencies: &self.external_dependencies,
+            },
+            self.algorithm,
+        )
+    }
+
+    /// Build a manifest that records the individual inputs of the hash, so
+    /// that the hash can later be explained.
+    pub(crate) fn manifest(&self) -> HashManifest {
+        // There is no reason for these conversions to ever fail so unwrap is
+        // fine.
+        let mut fields = serde_json::to_value(&self.fields).unwrap();
+
+        if let serde_json::Value::Object(fields) = &mut fields {
+            fields.insert(
+                "dist_targets".to_string(),
+                serde_json::to_value(self.dist_targets).unwrap(),
+            );
+            fields.insert(
+                "external_dependencies".to_string(),
+                serde_json::to_value(&self.external_dependencies).unwrap(),
+            );
+            fields.insert(
+                "build_config".to_string(),
+                serde_json::to_value(self.build_config).unwrap(),
+            );
+        }
+
+        HashManifest {
+            hash: self.hash(),
+            sources: self
+                .sources
+                .iter()
+                .map(|(path, file)| (path.clone(), file.to_string()))
+                .collect(),
+            dependencies: self
+                .direct_link_names
+                .iter()
+                .map(ToString::to_string)
+                .zip(self.direct_links.iter().cloned())
+                .collect(),
+            fields,
+        }
+    }
+}
+
+fn hash_serializable(value: &impl Serialize, algorithm: HashAlgorithm) -> String {
+    let mut hasher = algorithm.hasher();
+
+    // There is no reason for this write to ever fail so unwrap is fine.
+    serde_json::to_writer(&mut hasher, value).unwrap();
+
+    format!("{}:{}", HashFormat::current(algorithm), hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_format_parse() {
+        let format = |algorithm, version| Some(HashFormat { algorithm, version });
+
+        assert_eq!(
+            HashFormat::parse("sha256:abcdef"),
+            format(HashAlgorithm::Sha256, 1)
+        );
+        assert_eq!(
+            HashFormat::parse("sha256-v2:abcdef"),
+            format(HashAlgorithm::Sha256, 2)
+        );
+        assert_eq!(
+            HashFormat::parse("blake3-v12:abcdef"),
+            format(HashAlgorithm::Blake3, 12)
+        );
+
+        assert_eq!(HashFormat::parse("abcdef"), None);
+        assert_eq!(HashFormat::parse("blake3:abcdef"), None);
+        assert_eq!(HashFormat::parse("md5-v2:abcdef"), None);
+        assert_eq!(HashFormat::parse("sha256-vx:abcdef"), None);
+    }
 
-        format!("sha256:{:x}", state.finalize())
+    #[test]
+    fn test_hash_format_display() {
+        assert_eq!(
+            HashFormat::current(HashAlgorithm::Blake3).to_string(),
+            format!("blake3-v{HASH_FORMAT_VERSION}")
+        );
     }
 }
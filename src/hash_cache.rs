@@ -0,0 +1,459 @@
+//! A persistent cache of file digests.
+//!
+//! Digesting all the sources of all the packages on every invocation is slow,
+//! so digests are cached on disk, under the Cargo target directory, keyed by
+//! the metadata of the digested files. An entry is only used if the size,
+//! modification time and inode of its file did not change. Entries of files
+//! that no longer exist, or that were not used for a while, are dropped, so
+//! that the cache does not grow unbounded.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sources::{DigestOptions, Sources},
+    Error, Result,
+};
+
+/// The version of the cache file format.
+///
+/// Cache files with a different version are discarded.
+const HASH_CACHE_VERSION: u32 = 2;
+
+/// Files modified less than this long before being digested are not cached,
+/// as a subsequent modification could go unnoticed if it happened within the
+/// resolution of the file system timestamps.
+const RACY_MODIFICATION_DELAY: Duration = Duration::from_secs(2);
+
+/// Entries that were not used for this long are dropped.
+///
+/// Runs usually only digest some of the packages: entries are kept well past
+/// a single run so that the next run digesting all of them still benefits
+/// from the cache.
+const MAX_UNUSED_AGE: Duration = Duration::from_hours(720);
+
+/// The resolution of the last use time of entries, which bounds how often an
+/// unchanged cache is written back to disk.
+const LAST_USED_RESOLUTION: Duration = Duration::from_hours(24);
+
+type CacheKey = (PathBuf, DigestOptions);
+
+#[derive(Debug, Default)]
+pub(crate) struct HashCache {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    dirty: AtomicBool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileKey {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    inode: u64,
+}
+
+impl FileKey {
+    fn new(path: &Path) -> std::io::Result<(Self, SystemTime)> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        let mtime = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+
+        Ok((
+            Self {
+                size: metadata.len(),
+                mtime_secs: mtime.as_secs(),
+                mtime_nanos: mtime.subsec_nanos(),
+                inode,
+            },
+            modified,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key: FileKey,
+    digest: String,
+    /// When the entry was last used, in seconds since the Unix epoch.
+    last_used_secs: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<CacheFileEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFileEntry {
+    path: PathBuf,
+    #[serde(flatten)]
+    options: DigestOptions,
+    #[serde(flatten)]
+    entry: CacheEntry,
+}
+
+impl HashCache {
+    /// Load the cache stored at the specified path.
+    ///
+    /// A missing, stale or corrupt cache file results in an empty cache.
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read(&path) {
+            Ok(data) => match serde_json::from_slice::<CacheFile>(&data) {
+                Ok(cache_file) if cache_file.version == HASH_CACHE_VERSION => cache_file
+                    .entries
+                    .into_iter()
+                    .map(|entry| ((entry.path, entry.options), entry.entry))
+                    .collect(),
+                Ok(cache_file) => {
+                    debug!(
+                        "Ignoring hash cache `{}` with unsupported version {}",
+                        path.display(),
+                        cache_file.version
+                    );
+
+                    HashMap::new()
+                }
+                Err(err) => {
+                    debug!("Ignoring corrupt hash cache `{}`: {}", path.display(), err);
+
+                    HashMap::new()
+                }
+            },
+            Err(err) => {
+                debug!("No hash cache loaded from `{}`: {}", path.display(), err);
+
+                HashMap::new()
+            }
+        };
+
+        debug!("Loaded {} hash cache entries", entries.len());
+
+        Self {
+            path: Some(path),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a cache that is never read from nor written to disk.
+    pub(crate) fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Get the digest of the specified file, from the cache if the file did
+    /// not change since it was last digested.
+    pub(crate) fn digest_file(&self, path: &Path, options: DigestOptions) -> Result<String> {
+        if self.path.is_none() {
+            return Sources::digest_file(path, options);
+        }
+
+        // If the metadata cannot be read, we let the actual read fail.
+        let Ok((key, modified)) = FileKey::new(path) else {
+            return Sources::digest_file(path, options);
+        };
+
+        let cache_key = (path.to_path_buf(), options);
+        let now_secs = unix_secs(SystemTime::now());
+
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&cache_key) {
+            if entry.key == key {
+                if now_secs >= entry.last_used_secs + LAST_USED_RESOLUTION.as_secs() {
+                    entry.last_used_secs = now_secs;
+                    self.dirty.store(true, Ordering::Relaxed);
+                }
+
+                return Ok(entry.digest.clone());
+            }
+        }
+
+        let digest = Sources::digest_file(path, options)?;
+
+        let is_racy = SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age < RACY_MODIFICATION_DELAY);
+
+        if !is_racy {
+            self.entries.lock().unwrap().insert(
+                cache_key,
+                CacheEntry {
+                    key,
+                    digest: digest.clone(),
+                    last_used_secs: now_secs,
+                },
+            );
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        Ok(digest)
+    }
+
+    /// Save the cache to disk, if it was modified, dropping the entries of
+    /// files that no longer exist or that were not used for a while.
+    pub(crate) fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        let min_last_used_secs =
+            unix_secs(SystemTime::now()).saturating_sub(MAX_UNUSED_AGE.as_secs());
+
+        let len = entries.len();
+        entries
+            .retain(|(path, _), entry| entry.last_used_secs >= min_last_used_secs && path.exists());
+
+        if len != entries.len() {
+            debug!("Pruned {} stale hash cache entries", len - entries.len());
+
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let cache_file = CacheFile {
+            version: HASH_CACHE_VERSION,
+            entries: entries
+                .iter()
+                .map(|((path, options), entry)| CacheFileEntry {
+                    path: path.clone(),
+                    options: *options,
+                    entry: entry.clone(),
+                })
+                .collect(),
+        };
+
+        debug!(
+            "Saving {} hash cache entries to `{}`",
+            cache_file.entries.len(),
+            path.display()
+        );
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        std::fs::create_dir_all(dir)
+            .map_err(|err| Error::new("failed to create hash cache directory").with_source(err))?;
+
+        // Write to a temporary file - unique to this invocation - first so that
+        // concurrent invocations never read nor write a partially written cache.
+        let mut tmp_file = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|err| Error::new("failed to create hash cache file").with_source(err))?;
+
+        tmp_file
+            .write_all(&serde_json::to_vec(&cache_file).unwrap())
+            .map_err(|err| Error::new("failed to write hash cache").with_source(err))?;
+
+        tmp_file
+            .persist(path)
+            .map_err(|err| Error::new("failed to write hash cache").with_source(err))?;
+
+        self.dirty.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+
+    const OPTIONS: DigestOptions = DigestOptions {
+        algorithm: HashAlgorithm::Sha256,
+        normalize_line_endings: false,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-monorepo-hash-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Write a file with a modification time old enough for it to be cached.
+    fn write_old(path: &Path, contents: &str, age_secs: u64) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    /// Replace the cached digest of a file, so that cache hits can be told
+    /// apart from actual digests.
+    fn poison(cache: &HashCache, path: &Path) {
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut(&(path.to_path_buf(), OPTIONS))
+            .unwrap()
+            .digest = "cached".to_string();
+    }
+
+    #[test]
+    fn test_digest_file_invalidation() {
+        let dir = temp_dir("invalidation");
+        let cache = HashCache::load(dir.join("cache.json"));
+        let path = dir.join("file.txt");
+
+        // Recently modified files are not cached.
+        std::fs::write(&path, "racy").unwrap();
+        cache.digest_file(&path, OPTIONS).unwrap();
+        assert!(cache.entries.lock().unwrap().is_empty());
+
+        write_old(&path, "hello", 3600);
+        cache.digest_file(&path, OPTIONS).unwrap();
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+
+        poison(&cache, &path);
+        assert_eq!(cache.digest_file(&path, OPTIONS).unwrap(), "cached");
+
+        // Size change.
+        write_old(&path, "hello!", 3600);
+        assert_ne!(cache.digest_file(&path, OPTIONS).unwrap(), "cached");
+
+        // Modification time change, with the same size.
+        poison(&cache, &path);
+        write_old(&path, "hello!", 7200);
+        assert_ne!(cache.digest_file(&path, OPTIONS).unwrap(), "cached");
+
+        // Inode change, with the same size and modification time.
+        #[cfg(unix)]
+        {
+            let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+            let other_path = dir.join("other.txt");
+            std::fs::write(&other_path, "hello?").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&other_path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+
+            poison(&cache, &path);
+            std::fs::rename(&other_path, &path).unwrap();
+            assert_ne!(cache.digest_file(&path, OPTIONS).unwrap(), "cached");
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_save_prunes_stale_entries() {
+        let dir = temp_dir("prune");
+        let cache_path = dir.join("cache.json");
+        let used = dir.join("used.txt");
+        let unused = dir.join("unused.txt");
+        let old = dir.join("old.txt");
+        let removed = dir.join("removed.txt");
+
+        for path in [&used, &unused, &old, &removed] {
+            write_old(path, "contents", 3600);
+        }
+
+        let cache = HashCache::load(cache_path.clone());
+        for path in [&used, &unused, &old, &removed] {
+            cache.digest_file(path, OPTIONS).unwrap();
+        }
+        cache.save().unwrap();
+
+        // Entries not used by a run are kept, unless they were not used for a
+        // long time or their file was removed.
+        let cache = HashCache::load(cache_path.clone());
+        assert_eq!(cache.entries.lock().unwrap().len(), 4);
+
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut(&(old.clone(), OPTIONS))
+            .unwrap()
+            .last_used_secs -= MAX_UNUSED_AGE.as_secs() + 1;
+        std::fs::remove_file(&removed).unwrap();
+
+        cache.digest_file(&used, OPTIONS).unwrap();
+        cache.save().unwrap();
+
+        let cache = HashCache::load(cache_path);
+        let mut paths: Vec<_> = cache
+            .entries
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, [unused, used]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_save_keeps_unchanged_cache() {
+        let dir = temp_dir("unchanged");
+        let cache_path = dir.join("cache.json");
+        let path = dir.join("file.txt");
+
+        write_old(&path, "contents", 3600);
+
+        let cache = HashCache::load(cache_path.clone());
+        cache.digest_file(&path, OPTIONS).unwrap();
+        cache.save().unwrap();
+
+        let modified = std::fs::metadata(&cache_path).unwrap().modified().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Using entries that were used recently does not rewrite the cache.
+        let cache = HashCache::load(cache_path.clone());
+        cache.digest_file(&path, OPTIONS).unwrap();
+        cache.save().unwrap();
+
+        assert_eq!(
+            std::fs::metadata(&cache_path).unwrap().modified().unwrap(),
+            modified
+        );
+        assert_eq!(
+            std::fs::read_dir(&dir).unwrap().count(),
+            2,
+            "no temporary file is left behind"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,275 @@
+//! Records of the individual inputs of package hashes.
+//!
+//! A hash manifest is persisted for every tagged version of a package so that
+//! a later mismatch between the current hash and the tag can be explained.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{hash::HashFormat, Error, ErrorContext, Result};
+
+/// The name of the file, next to the package manifest, that holds the hash
+/// manifests of all the tagged versions of a package.
+pub(crate) const HASH_MANIFESTS_FILE_NAME: &str = "monorepo-hashes.json";
+
+/// The individual inputs of a package hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HashManifest {
+    pub hash: String,
+    /// The description of each source file - its digest and executable bit or
+    /// its symbolic link target - keyed by its relative path.
+    pub sources: BTreeMap<String, String>,
+    /// The hash - or id for non-workspace packages - of each direct
+    /// dependency, keyed by dependency name.
+    pub dependencies: BTreeMap<String, String>,
+    /// The package metadata fields and distribution targets configuration.
+    pub fields: serde_json::Value,
+}
+
+impl HashManifest {
+    /// Compute the list of changes from `previous` to `self`.
+    pub(crate) fn changes_since(&self, previous: &Self) -> Vec<HashChange> {
+        let mut changes = Vec::new();
+
+        for path in previous
+            .sources
+            .keys()
+            .chain(self.sources.keys())
+            .collect::<BTreeSet<_>>()
+        {
+            match (previous.sources.get(path), self.sources.get(path)) {
+                (None, Some(_)) => changes.push(HashChange::SourceAdded(path.clone())),
+                (Some(_), None) => changes.push(HashChange::SourceRemoved(path.clone())),
+                (Some(previous), Some(current)) if previous != current => {
+                    changes.push(HashChange::SourceModified(path.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        for name in previous
+            .dependencies
+            .keys()
+            .chain(self.dependencies.keys())
+            .collect::<BTreeSet<_>>()
+        {
+            match (previous.dependencies.get(name), self.dependencies.get(name)) {
+                (None, Some(_)) => changes.push(HashChange::DependencyAdded(name.clone())),
+                (Some(_), None) => changes.push(HashChange::DependencyRemoved(name.clone())),
+                (Some(previous), Some(current)) if previous != current => {
+                    changes.push(HashChange::DependencyChanged {
+                        name: name.clone(),
+                        in_workspace: HashFormat::parse(current).is_some(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        diff_fields("", &previous.fields, &self.fields, &mut changes);
+
+        changes
+    }
+}
+
+fn diff_fields(
+    path: &str,
+    previous: &serde_json::Value,
+    current: &serde_json::Value,
+    changes: &mut Vec<HashChange>,
+) {
+    match (previous, current) {
+        (serde_json::Value::Object(previous), serde_json::Value::Object(current)) => {
+            for key in previous
+                .keys()
+                .chain(current.keys())
+                .collect::<BTreeSet<_>>()
+            {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+
+                diff_fields(
+                    &path,
+                    previous.get(key).unwrap_or(&serde_json::Value::Null),
+                    current.get(key).unwrap_or(&serde_json::Value::Null),
+                    changes,
+                );
+            }
+        }
+        (previous, current) if previous != current => {
+            changes.push(HashChange::FieldChanged(path.to_string()));
+        }
+        _ => {}
+    }
+}
+
+/// A change in the inputs of a package hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashChange {
+    SourceAdded(String),
+    SourceRemoved(String),
+    SourceModified(String),
+    DependencyAdded(String),
+    DependencyRemoved(String),
+    DependencyChanged { name: String, in_workspace: bool },
+    FieldChanged(String),
+}
+
+impl Display for HashChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SourceAdded(path) => write!(f, "{path} added"),
+            Self::SourceRemoved(path) => write!(f, "{path} removed"),
+            Self::SourceModified(path) => write!(f, "{path} modified"),
+            Self::DependencyAdded(name) => write!(f, "dependency {name} added"),
+            Self::DependencyRemoved(name) => write!(f, "dependency {name} removed"),
+            Self::DependencyChanged {
+                name,
+                in_workspace: true,
+            } => write!(f, "dependency {name} hash changed"),
+            Self::DependencyChanged {
+                name,
+                in_workspace: false,
+            } => write!(f, "dependency {name} changed"),
+            Self::FieldChanged(path) => write!(f, "{path} changed"),
+        }
+    }
+}
+
+/// The hash manifests of all the tagged versions of a package.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct HashManifests(BTreeMap<semver::Version, HashManifest>);
+
+impl HashManifests {
+    /// Load the hash manifests of the package at the specified root.
+    ///
+    /// If no manifests were ever recorded, an empty set is returned.
+    pub(crate) fn load(package_root: &Path) -> Result<Self> {
+        let path = Self::path(package_root);
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(Error::new("failed to read hash manifests").with_source(err));
+            }
+        };
+
+        serde_json::from_slice(&data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to parse hash manifests",
+                format!(
+                    "The hash manifests file `{}` is invalid. You may need to re-tag the package.",
+                    path.display()
+                ),
+            )
+    }
+
+    /// Save the hash manifests of the package at the specified root.
+    pub(crate) fn save(&self, package_root: &Path) -> Result<()> {
+        let mut data = serde_json::to_string_pretty(&self).unwrap();
+        data.push('\n');
+
+        std::fs::write(Self::path(package_root), data)
+            .map_err(|err| Error::new("failed to write hash manifests").with_source(err))
+    }
+
+    pub(crate) fn get(&self, version: &semver::Version) -> Option<&HashManifest> {
+        self.0.get(version)
+    }
+
+    pub(crate) fn insert(&mut self, version: semver::Version, manifest: HashManifest) {
+        self.0.insert(version, manifest);
+    }
+
+    fn path(package_root: &Path) -> PathBuf {
+        package_root.join(HASH_MANIFESTS_FILE_NAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> HashManifest {
+        HashManifest {
+            hash: "sha256-v2:0".to_string(),
+            sources: [
+                ("src/lib.rs".to_string(), "a".to_string()),
+                ("src/main.rs".to_string(), "b".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            dependencies: [
+                ("mylib".to_string(), "sha256-v2:1".to_string()),
+                ("anyhow".to_string(), "anyhow 1.0.0".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            fields: serde_json::json!({
+                "version": "0.1.0",
+                "dist_targets": {
+                    "simple-docker": {
+                        "type": "docker",
+                        "template": "FROM ubuntu:20.04",
+                    },
+                },
+            }),
+        }
+    }
+
+    #[test]
+    fn test_changes_since_identical() {
+        assert!(manifest().changes_since(&manifest()).is_empty());
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let previous = manifest();
+        let mut current = manifest();
+
+        current
+            .sources
+            .insert("src/lib.rs".to_string(), "c".to_string());
+        current.sources.remove("src/main.rs");
+        current
+            .sources
+            .insert("src/new.rs".to_string(), "d".to_string());
+        current
+            .dependencies
+            .insert("mylib".to_string(), "sha256-v2:2".to_string());
+        current
+            .dependencies
+            .insert("anyhow".to_string(), "anyhow 1.0.1".to_string());
+        current.fields["dist_targets"]["simple-docker"]["template"] =
+            serde_json::json!("FROM ubuntu:22.04");
+
+        let changes: Vec<_> = current
+            .changes_since(&previous)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                "src/lib.rs modified",
+                "src/main.rs removed",
+                "src/new.rs added",
+                "dependency anyhow changed",
+                "dependency mylib hash changed",
+                "dist_targets.simple-docker.template changed",
+            ]
+        );
+    }
+}
@@ -60,18 +60,43 @@
 #![allow(clippy::implicit_hasher, clippy::missing_errors_doc)]
 
 mod aws_lambda;
+mod build_config;
+mod change_explanation;
+mod ci_reporter;
 mod context;
+mod dep_info;
 mod dist_target;
 mod docker;
 mod errors;
+mod git;
 mod hash;
+mod hash_cache;
+mod hash_manifest;
+mod lockfile;
+mod manifest_changes;
+mod matrix;
 mod metadata;
 mod package;
+mod package_record;
+pub mod reporter;
+pub mod run_log;
 mod rust;
+mod selection;
 mod sources;
 mod term;
+#[cfg(test)]
+mod test_workspace;
+mod triggers;
 
+pub use change_explanation::{ChangeExplanation, DirectChange};
+pub use ci_reporter::{CiProvider, CiReporter};
 pub use context::{Context, ContextBuilder, Mode, Options};
 pub(crate) use errors::ErrorContext;
 pub use errors::{Error, Result};
+pub use git::{GitChanges, GitRange, DEFAULT_MERGE_BASE_BRANCH};
+pub use hash::{HashAlgorithm, HashFormat};
+pub use hash_manifest::HashChange;
+pub use matrix::{github_matrix, gitlab_pipeline, MatrixEntry, GITLAB_JOB_TEMPLATE};
 pub use package::Package;
+pub use package_record::{DistTargetRecord, PackageRecord};
+pub use selection::{PackageSelection, PackageSpec};
@@ -0,0 +1,289 @@
+//! Cargo lockfile parsing.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{Error, ErrorContext, Result};
+
+/// The resolved packages of a Cargo lockfile.
+#[derive(Debug, Default)]
+pub(crate) struct Lockfile {
+    checksums: BTreeMap<LockedPackageKey, String>,
+    /// The dependencies of each package, as they appear in the lockfile.
+    dependencies: BTreeMap<LockedPackageKey, Vec<String>>,
+}
+
+/// The identity of a package in a lockfile: its name, version and source.
+type LockedPackageKey = (String, String, Option<String>);
+
+impl Lockfile {
+    /// Load the lockfile at the specified path.
+    ///
+    /// A missing lockfile results in an empty lockfile.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(Error::new("failed to read Cargo.lock").with_source(err)),
+        };
+
+        Self::parse(&data).with_context(format!("failed to parse lockfile `{}`", path.display()))
+    }
+
+    pub(crate) fn parse(data: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct RawLockfile {
+            #[serde(default)]
+            package: Vec<RawPackage>,
+            #[serde(default)]
+            metadata: BTreeMap<String, String>,
+        }
+
+        #[derive(Deserialize)]
+        struct RawPackage {
+            name: String,
+            version: String,
+            source: Option<String>,
+            checksum: Option<String>,
+            #[serde(default)]
+            dependencies: Vec<String>,
+        }
+
+        let raw: RawLockfile = toml::from_str(data).map_err(Error::from_source)?;
+
+        // Lockfiles in the version 1 format store the checksums in a separate
+        // `[metadata]` table, with keys like `checksum <name> <version> (<source>)`.
+        let mut checksums: BTreeMap<_, _> = raw
+            .metadata
+            .into_iter()
+            .filter_map(|(key, checksum)| {
+                let mut parts = key.strip_prefix("checksum ")?.splitn(3, ' ');
+                let name = parts.next()?.to_string();
+                let version = parts.next()?.to_string();
+                let source = parts
+                    .next()
+                    .and_then(|source| source.strip_prefix('('))
+                    .and_then(|source| source.strip_suffix(')'))
+                    .map(ToString::to_string);
+
+                Some(((name, version, source), checksum))
+            })
+            .collect();
+
+        let mut dependencies = BTreeMap::new();
+
+        for package in raw.package {
+            let key = (package.name, package.version, package.source);
+
+            if let Some(checksum) = package.checksum {
+                checksums.insert(key.clone(), checksum);
+            }
+
+            dependencies.insert(key, package.dependencies);
+        }
+
+        Ok(Self {
+            checksums,
+            dependencies,
+        })
+    }
+
+    /// Get the checksum of the specified package, if it has one.
+    ///
+    /// Only packages coming from a registry have checksums.
+    pub(crate) fn checksum(&self, name: &str, version: &str, source: Option<&str>) -> Option<&str> {
+        self.checksums
+            .get(&(
+                name.to_string(),
+                version.to_string(),
+                source.map(ToString::to_string),
+            ))
+            .map(String::as_str)
+    }
+
+    /// Get the resolved dependency closure of the workspace package with the
+    /// specified name, along with the checksum of each package.
+    ///
+    /// The package itself is part of its closure. If the package is not in
+    /// the lockfile, the closure is empty.
+    pub(crate) fn dependency_closure(
+        &self,
+        name: &str,
+    ) -> BTreeSet<(&LockedPackageKey, Option<&str>)> {
+        let mut closure = BTreeSet::new();
+        let mut queue: Vec<_> = self
+            .packages_named(name)
+            .filter(|(_, _, source)| source.is_none())
+            .collect();
+
+        while let Some(key) = queue.pop() {
+            if !closure.insert((key, self.checksums.get(key).map(String::as_str))) {
+                continue;
+            }
+
+            queue.extend(
+                self.dependencies[key]
+                    .iter()
+                    .filter_map(|dependency| self.resolve_dependency(dependency)),
+            );
+        }
+
+        closure
+    }
+
+    fn packages_named<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a LockedPackageKey> {
+        let name = name.to_string();
+
+        self.dependencies
+            .range((name.clone(), String::new(), None)..)
+            .map(|(key, _)| key)
+            .take_while(move |(key_name, _, _)| *key_name == name)
+    }
+
+    /// Resolve a dependency of a package, which is written `<name>`,
+    /// `<name> <version>` or `<name> <version> (<source>)` depending on how
+    /// ambiguous the name is.
+    fn resolve_dependency(&self, dependency: &str) -> Option<&LockedPackageKey> {
+        let mut parts = dependency.splitn(3, ' ');
+        let name = parts.next()?;
+        let version = parts.next();
+        let source = parts
+            .next()
+            .and_then(|source| source.strip_prefix('('))
+            .and_then(|source| source.strip_suffix(')'));
+
+        self.packages_named(name)
+            .find(|(_, key_version, key_source)| {
+                version.is_none_or(|version| key_version == version)
+                    && source.is_none_or(|source| key_source.as_deref() == Some(source))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    #[test]
+    fn test_parse_lockfile() {
+        let lockfile = Lockfile::parse(
+            r#"
+version = 3
+
+[[package]]
+name = "anyhow"
+version = "1.0.51"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "8b26702f315f53b6071259e15dd9d64528213b44d61de1ec926eca7715d62203"
+
+[[package]]
+name = "mylib"
+version = "0.1.0"
+dependencies = [
+ "anyhow",
+]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lockfile.checksum("anyhow", "1.0.51", Some(CRATES_IO)),
+            Some("8b26702f315f53b6071259e15dd9d64528213b44d61de1ec926eca7715d62203")
+        );
+        assert_eq!(lockfile.checksum("anyhow", "1.0.52", Some(CRATES_IO)), None);
+        assert_eq!(lockfile.checksum("mylib", "0.1.0", None), None);
+    }
+
+    #[test]
+    fn test_parse_lockfile_v1() {
+        let lockfile = Lockfile::parse(
+            r#"
+[[package]]
+name = "anyhow"
+version = "1.0.51"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[metadata]
+"checksum anyhow 1.0.51 (registry+https://github.com/rust-lang/crates.io-index)" = "8b26702f315f53b6071259e15dd9d64528213b44d61de1ec926eca7715d62203"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lockfile.checksum("anyhow", "1.0.51", Some(CRATES_IO)),
+            Some("8b26702f315f53b6071259e15dd9d64528213b44d61de1ec926eca7715d62203")
+        );
+    }
+
+    #[test]
+    fn test_dependency_closure() {
+        let lockfile = |anyhow_version: &str| {
+            Lockfile::parse(&format!(
+                r#"
+version = 3
+
+[[package]]
+name = "anyhow"
+version = "{anyhow_version}"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "{anyhow_version}"
+
+[[package]]
+name = "log"
+version = "0.4.14"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "51b9bbe6c47d51fc3e1a9b945965946b4c44142ab8792c50835a980d362c2710"
+
+[[package]]
+name = "mylib"
+version = "0.1.0"
+dependencies = [
+ "anyhow {anyhow_version} (registry+https://github.com/rust-lang/crates.io-index)",
+]
+
+[[package]]
+name = "mybinary"
+version = "0.1.0"
+dependencies = [
+ "log",
+ "mylib",
+]
+
+[[package]]
+name = "mytool"
+version = "0.1.0"
+dependencies = [
+ "log",
+]
+"#
+            ))
+            .unwrap()
+        };
+
+        let before = lockfile("1.0.51");
+        let after = lockfile("1.0.52");
+
+        assert_eq!(before.dependency_closure("mylib").len(), 2);
+        assert_eq!(before.dependency_closure("mybinary").len(), 4);
+        assert!(before.dependency_closure("unknown").is_empty());
+
+        assert_ne!(
+            before.dependency_closure("mylib"),
+            after.dependency_closure("mylib")
+        );
+        assert_ne!(
+            before.dependency_closure("mybinary"),
+            after.dependency_closure("mybinary")
+        );
+        assert_eq!(
+            before.dependency_closure("mytool"),
+            after.dependency_closure("mytool")
+        );
+    }
+}
@@ -56,29 +56,65 @@
 
 use cargo_monorepo::{Context, Mode, Options, Package};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use log::debug;
+use itertools::Itertools;
+use log::{debug, warn};
 use std::{
+    collections::BTreeMap,
     env,
     fmt::{Debug, Formatter},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-use cargo_monorepo::{Error, Result};
+use cargo_monorepo::{
+    github_matrix, gitlab_pipeline,
+    reporter::{self, HumanReporter, JsonReporter, Reporter, Verbosity},
+    run_log, CiProvider, CiReporter, Error, GitChanges, MatrixEntry, PackageSpec, Result,
+    DEFAULT_MERGE_BASE_BRANCH,
+};
 
 const ARG_DEBUG: &str = "debug";
 const ARG_RELEASE: &str = "release";
 const ARG_MANIFEST_PATH: &str = "manifest-path";
 const ARG_VERBOSE: &str = "verbose";
+const ARG_QUIET: &str = "quiet";
+const ARG_LOG_FILE: &str = "log-file";
 const ARG_DRY_RUN: &str = "dry-run";
 const ARG_FORCE: &str = "force";
+const ARG_NO_CACHE: &str = "no-cache";
 const ARG_PACKAGE: &str = "package";
 const ARG_PACKAGES: &str = "packages";
 const ARG_CHANGED_SINCE_GIT_REF: &str = "changed-since-git-ref";
+const ARG_CHANGED_SINCE_MERGE_BASE: &str = "changed-since-merge-base";
+const ARG_CHANGED_SINCE_TAG: &str = "changed-since-tag";
+const ARG_EXCLUDE: &str = "exclude";
+const ARG_WITH_DEPENDENCIES: &str = "with-dependencies";
+const ARG_WITH_DEPENDANTS: &str = "with-dependants";
+const ARG_HAS_DIST_TARGET: &str = "has-dist-target";
+const ARG_INCLUDE_UNTRACKED: &str = "include-untracked";
+const ARG_STAGED_ONLY: &str = "staged-only";
 const ARG_COMMAND: &str = "command";
+const ARG_EXPLAIN: &str = "explain";
+const ARG_BUILD_CONFIG: &str = "build-config";
+const ARG_WHY: &str = "why";
+const ARG_BY_GROUP: &str = "by-group";
+const ARG_GROUP: &str = "group";
+const ARG_FORMAT: &str = "format";
+const ARG_GITLAB_INCLUDE: &str = "gitlab-include";
+const ARG_MESSAGE_FORMAT: &str = "message-format";
 const ARG_REMAINING_ARGS: &str = "remaining-args";
 
+const FORMAT_TEXT: &str = "text";
+const FORMAT_JSON: &str = "json";
+const FORMAT_YAML: &str = "yaml";
+const FORMAT_TSV: &str = "tsv";
+const FORMAT_GITHUB: &str = "github";
+const FORMAT_GITLAB: &str = "gitlab";
+const MESSAGE_FORMAT_AUTO: &str = "auto";
+const MESSAGE_FORMAT_HUMAN: &str = "human";
+const MESSAGE_FORMAT_JSON: &str = "json";
+
 const SUB_COMMAND_HASH: &str = "hash";
 const SUB_COMMAND_LIST: &str = "list";
 const SUB_COMMAND_BUILD: &str = "build";
@@ -88,6 +124,8 @@ const SUB_COMMAND_BUILD_DIST: &str = "build-dist";
 const SUB_COMMAND_PUBLISH_DIST: &str = "publish-dist";
 const SUB_COMMAND_EXEC: &str = "exec";
 const SUB_COMMAND_TAG: &str = "tag";
+const SUB_COMMAND_MIGRATE_TAGS: &str = "migrate-tags";
+const SUB_COMMAND_MATRIX: &str = "matrix";
 
 struct MainError(Error);
 
@@ -158,19 +196,86 @@ impl PackageSelection for clap::App<'_, '_> {
                 .takes_value(true)
                 .multiple(true)
                 .require_delimiter(true)
-                .conflicts_with(ARG_CHANGED_SINCE_GIT_REF)
-                .help("A list of packages to execute the command for, separated by commas"),
+                .help("A list of packages to execute the command for, separated by commas. Glob patterns and `name@version` specs are supported"),
+        )
+        .arg(
+            Arg::with_name(ARG_GROUP)
+                .long(ARG_GROUP)
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .help("Only select the packages that belong to one of the specified groups, separated by commas"),
+        )
+        .arg(
+            Arg::with_name(ARG_EXCLUDE)
+                .long(ARG_EXCLUDE)
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .help("A list of packages to exclude from the selection, separated by commas. Glob patterns and `name@version` specs are supported"),
+        )
+        .arg(
+            Arg::with_name(ARG_WITH_DEPENDENCIES)
+                .long(ARG_WITH_DEPENDENCIES)
+                .help("Also select the workspace packages the selected packages depend on"),
+        )
+        .arg(
+            Arg::with_name(ARG_WITH_DEPENDANTS)
+                .long(ARG_WITH_DEPENDANTS)
+                .help("Also select the workspace packages that depend on the selected packages"),
+        )
+        .arg(
+            Arg::with_name(ARG_HAS_DIST_TARGET)
+                .long(ARG_HAS_DIST_TARGET)
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .possible_values(&["docker", "aws-lambda"])
+                .help("Only select the packages with a distribution target of one of the specified types"),
         )
         .arg(
             Arg::with_name(ARG_CHANGED_SINCE_GIT_REF)
                 .long(ARG_CHANGED_SINCE_GIT_REF)
                 .short("s")
                 .takes_value(true)
-                .conflicts_with(ARG_PACKAGES)
+                .value_name("range")
+                .conflicts_with_all(&[ARG_CHANGED_SINCE_MERGE_BASE, ARG_CHANGED_SINCE_TAG])
+                .help(
+                    "Only operate on the packages with changes since the specified Git reference, or in the specified `A..B` or `A...B` range",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_CHANGED_SINCE_MERGE_BASE)
+                .long(ARG_CHANGED_SINCE_MERGE_BASE)
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .value_name("branch")
+                .conflicts_with_all(&[ARG_CHANGED_SINCE_GIT_REF, ARG_CHANGED_SINCE_TAG])
                 .help(
-                    "Only operate on the packages with changes since the specified Git reference",
+                    "Only operate on the packages with changes since the merge-base of the specified branch - `origin/main` by default - and HEAD",
                 ),
         )
+        .arg(
+            Arg::with_name(ARG_CHANGED_SINCE_TAG)
+                .long(ARG_CHANGED_SINCE_TAG)
+                .visible_alias("untagged")
+                .conflicts_with_all(&[ARG_CHANGED_SINCE_GIT_REF, ARG_CHANGED_SINCE_MERGE_BASE])
+                .help("Only operate on the packages whose hash does not match the tag of their current version"),
+        )
+        .arg(
+            Arg::with_name(ARG_INCLUDE_UNTRACKED)
+                .long(ARG_INCLUDE_UNTRACKED)
+                .requires(ARG_CHANGED_SINCE_GIT_REF)
+                .help("Consider untracked files as changes"),
+        )
+        .arg(
+            Arg::with_name(ARG_STAGED_ONLY)
+                .long(ARG_STAGED_ONLY)
+                .requires(ARG_CHANGED_SINCE_GIT_REF)
+                .conflicts_with(ARG_INCLUDE_UNTRACKED)
+                .help("Only consider the changes staged in the index, ignoring the working directory"),
+        )
     }
 }
 
@@ -208,8 +313,28 @@ fn get_matches() -> clap::ArgMatches<'static> {
                 .short("v")
                 .long(ARG_VERBOSE)
                 .required(false)
+                .multiple(true)
                 .global(true)
-                .help("Print debug information verbosely"),
+                .help("Print the output of the commands that are run - specify twice to also print debug information, three times for trace information"),
+        )
+        .arg(
+            Arg::with_name(ARG_QUIET)
+                .short("q")
+                .long(ARG_QUIET)
+                .required(false)
+                .multiple(true)
+                .global(true)
+                .conflicts_with(ARG_VERBOSE)
+                .help("Only print the skipped steps - specify twice to only print errors"),
+        )
+        .arg(
+            Arg::with_name(ARG_LOG_FILE)
+                .long(ARG_LOG_FILE)
+                .takes_value(true)
+                .value_name("path")
+                .required(false)
+                .global(true)
+                .help("Write the debug information, the progress messages and the output of all the commands that are run to the specified file, whatever is printed"),
         )
         .arg(
             Arg::with_name(ARG_DRY_RUN)
@@ -227,6 +352,22 @@ fn get_matches() -> clap::ArgMatches<'static> {
                 .global(true)
                 .help("Push artifacts even if they already exist - this can be dangerous"),
         )
+        .arg(
+            Arg::with_name(ARG_NO_CACHE)
+                .long(ARG_NO_CACHE)
+                .required(false)
+                .global(true)
+                .help("Do not use the on-disk cache of file digests"),
+        )
+        .arg(
+            Arg::with_name(ARG_MESSAGE_FORMAT)
+                .long(ARG_MESSAGE_FORMAT)
+                .takes_value(true)
+                .possible_values(&[MESSAGE_FORMAT_AUTO, MESSAGE_FORMAT_HUMAN, MESSAGE_FORMAT_JSON])
+                .default_value(MESSAGE_FORMAT_AUTO)
+                .global(true)
+                .help("The format of the progress messages: `json` reports them as JSON Lines on the standard output, `auto` uses the native format of the CI service the command runs under, if any, or `human` otherwise"),
+        )
         .arg(
             Arg::with_name(ARG_MANIFEST_PATH)
                 .short("m")
@@ -239,21 +380,79 @@ fn get_matches() -> clap::ArgMatches<'static> {
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_HASH)
                 .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_EXPLAIN)
+                        .long(ARG_EXPLAIN)
+                        .takes_value(true)
+                        .value_name("package")
+                        .conflicts_with_all(&[
+                            ARG_PACKAGES,
+                            ARG_CHANGED_SINCE_GIT_REF,
+                            ARG_CHANGED_SINCE_MERGE_BASE,
+                            ARG_CHANGED_SINCE_TAG,
+                        ])
+                        .help("Explain which inputs changed the hash of the specified package since its current version was tagged"),
+                )
+                .arg(
+                    Arg::with_name(ARG_BUILD_CONFIG)
+                        .long(ARG_BUILD_CONFIG)
+                        .conflicts_with(ARG_EXPLAIN)
+                        .help("List the build configuration inputs that were picked up for each package instead of printing its hash"),
+                )
+                .arg(
+                    Arg::with_name(ARG_FORMAT)
+                        .long(ARG_FORMAT)
+                        .takes_value(true)
+                        .possible_values(&[FORMAT_TEXT, FORMAT_JSON, FORMAT_YAML, FORMAT_TSV])
+                        .default_value(FORMAT_TEXT)
+                        .help("The output format: the structured formats give a full record of each package"),
+                )
                 .about("Print the hash of the specified package")
         )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_LIST)
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_WHY)
+                        .long(ARG_WHY)
+                        .help("Explain why each package is considered changed: either the files that changed in the package, or the dependency path through which it was pulled in"),
+                )
+                .arg(
+                    Arg::with_name(ARG_BY_GROUP)
+                        .long(ARG_BY_GROUP)
+                        .conflicts_with(ARG_WHY)
+                        .help("List the packages by group - a package that belongs to several groups is listed in each of them"),
+                )
                 .arg(
-                    Arg::with_name(ARG_CHANGED_SINCE_GIT_REF)
-                        .long(ARG_CHANGED_SINCE_GIT_REF)
-                        .short("s")
+                    Arg::with_name(ARG_FORMAT)
+                        .long(ARG_FORMAT)
                         .takes_value(true)
-                        .help(
-                            "Only list the packages with changes since the specified Git reference",
-                        ),
+                        .possible_values(&[FORMAT_TEXT, FORMAT_JSON, FORMAT_YAML, FORMAT_TSV])
+                        .default_value(FORMAT_TEXT)
+                        .help("The output format: unless listing by group or explaining changes, the structured formats give a full record of each package"),
                 )
                 .about("List all the packages in the current workspace"),
         )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_MATRIX)
+                .about("Print a CI job matrix with one entry per distribution target of the specified packages")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_FORMAT)
+                        .long(ARG_FORMAT)
+                        .takes_value(true)
+                        .possible_values(&[FORMAT_GITHUB, FORMAT_GITLAB])
+                        .default_value(FORMAT_GITHUB)
+                        .help("The output format: a GitHub Actions `strategy.matrix` as JSON or a GitLab child pipeline as YAML"),
+                )
+                .arg(
+                    Arg::with_name(ARG_GITLAB_INCLUDE)
+                        .long(ARG_GITLAB_INCLUDE)
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("A local file to include in the GitLab child pipeline, that defines the job template the generated jobs extend"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_BUILD_DIST)
                 .about("Build the distributable artifacts for the specified packages")
@@ -317,6 +516,12 @@ fn get_matches() -> clap::ArgMatches<'static> {
                 .about("Tag the current version of the package")
                 .arg(Arg::with_name(ARG_PACKAGE).help("A package to tag").required(true)),
         )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_MIGRATE_TAGS)
+                .about("Migrate the tags of the current versions of the specified packages to the current hash format, or of all packages if no packages are specified")
+                .long_about("Migrate the tags of the current versions of the specified packages to the current hash format, or of all packages if no packages are specified.\n\nOnly the tag of the current version of each package is migrated: the sources of previous versions are not available, so their hashes cannot be recomputed and their tags keep their original format.")
+                .with_package_selection(),
+        )
         .get_matches_from(args)
 }
 
@@ -377,59 +582,341 @@ fn make_options(matches: &ArgMatches<'_>) -> Options {
         dry_run: matches.is_present(ARG_DRY_RUN),
         force: matches.is_present(ARG_FORCE),
         verbose: matches.is_present(ARG_VERBOSE),
+        no_cache: matches.is_present(ARG_NO_CACHE),
         mode,
     }
 }
 
 fn select_packages<'g>(context: &'g Context, matches: &ArgMatches<'_>) -> Result<Vec<Package<'g>>> {
-    match matches.value_of(ARG_CHANGED_SINCE_GIT_REF) {
-        Some(git_ref) => context.resolve_changed_packages(git_ref),
-        None => match matches.values_of(ARG_PACKAGES) {
-            Some(packages_names) => context.resolve_packages_by_names(packages_names),
-            None => context.packages(),
-        },
+    package_selection(matches)?.resolve(context)
+}
+
+fn package_selection(matches: &ArgMatches<'_>) -> Result<cargo_monorepo::PackageSelection> {
+    let specs = |arg| {
+        matches
+            .values_of(arg)
+            .into_iter()
+            .flatten()
+            .map(str::parse)
+            .collect::<Result<Vec<PackageSpec>>>()
+    };
+
+    Ok(cargo_monorepo::PackageSelection {
+        specs: specs(ARG_PACKAGES)?,
+        groups: matches
+            .values_of(ARG_GROUP)
+            .into_iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect(),
+        exclude: specs(ARG_EXCLUDE)?,
+        changes: git_changes(matches)?,
+        untagged: matches.is_present(ARG_CHANGED_SINCE_TAG),
+        with_dependencies: matches.is_present(ARG_WITH_DEPENDENCIES),
+        with_dependants: matches.is_present(ARG_WITH_DEPENDANTS),
+        dist_target_types: matches
+            .values_of(ARG_HAS_DIST_TARGET)
+            .into_iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+fn list_packages_by_group(packages: &[Package<'_>], format: &str) {
+    #[derive(serde::Serialize)]
+    struct Group<'a> {
+        group: Option<&'a str>,
+        packages: Vec<&'a str>,
+    }
+
+    let mut groups: BTreeMap<Option<&str>, Vec<&str>> = BTreeMap::new();
+
+    for package in packages {
+        if package.groups().is_empty() {
+            groups.entry(None).or_default().push(package.name());
+        }
+
+        for group in package.groups() {
+            groups
+                .entry(Some(group.as_str()))
+                .or_default()
+                .push(package.name());
+        }
+    }
+
+    match format {
+        FORMAT_TEXT => {
+            for (group, packages) in groups {
+                println!("{}:", group.unwrap_or("(no group)"));
+
+                for package in packages {
+                    println!("    {package}");
+                }
+            }
+        }
+        FORMAT_TSV => {
+            for (group, packages) in groups {
+                for package in packages {
+                    println!("{}\t{}", group.unwrap_or_default(), package);
+                }
+            }
+        }
+        format => {
+            let groups: Vec<_> = groups
+                .into_iter()
+                .map(|(group, packages)| Group { group, packages })
+                .collect();
+
+            print_serialized(&groups, format);
+        }
+    }
+}
+
+/// Print the full records of the specified packages, whose hashes must have
+/// been computed already.
+fn print_package_records(packages: &[Package<'_>], format: &str) -> Result<()> {
+    let records = packages
+        .iter()
+        .map(Package::record)
+        .collect::<Result<Vec<_>>>()?;
+
+    if format == FORMAT_TSV {
+        for record in records {
+            println!("{}", record.to_tsv());
+        }
+    } else {
+        print_serialized(&records, format);
+    }
+
+    Ok(())
+}
+
+/// Print a value in one of the serialization formats: JSON or YAML.
+fn print_serialized(value: &impl serde::Serialize, format: &str) {
+    match format {
+        FORMAT_JSON => println!("{}", serde_json::to_string_pretty(value).unwrap()),
+        FORMAT_YAML => print!("{}", serde_yaml::to_string(value).unwrap()),
+        format => unreachable!("unsupported serialization format `{}`", format),
+    }
+}
+
+fn git_changes(matches: &ArgMatches<'_>) -> Result<Option<GitChanges>> {
+    if matches.is_present(ARG_CHANGED_SINCE_MERGE_BASE) {
+        let branch = matches
+            .value_of(ARG_CHANGED_SINCE_MERGE_BASE)
+            .unwrap_or(DEFAULT_MERGE_BASE_BRANCH);
+
+        return Ok(Some(GitChanges::since_merge_base(branch)));
     }
+
+    matches
+        .value_of(ARG_CHANGED_SINCE_GIT_REF)
+        .map(|range| {
+            Ok(GitChanges {
+                range: range.parse()?,
+                include_untracked: matches.is_present(ARG_INCLUDE_UNTRACKED),
+                staged_only: matches.is_present(ARG_STAGED_ONLY),
+            })
+        })
+        .transpose()
 }
 
 fn run() -> Result<()> {
     let matches = get_matches();
 
-    let mut log_level = log::LevelFilter::Off;
+    // `-v` shows the output of the commands, `-vv` adds the debug logs and
+    // `-vvv` the trace logs.
+    let log_level = match matches.occurrences_of(ARG_VERBOSE) {
+        0 | 1 if matches.is_present(ARG_DEBUG) => log::LevelFilter::Debug,
+        0 | 1 => log::LevelFilter::Off,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
 
-    if matches.is_present(ARG_DEBUG) {
-        log_level = log::LevelFilter::Debug;
+    if let Some(path) = matches.value_of(ARG_LOG_FILE) {
+        run_log::open(Path::new(path))?;
     }
 
-    env_logger::Builder::new().filter_level(log_level).init();
+    run_log::init_logger(env_logger::Builder::new().filter_level(log_level).build());
 
     debug!("Log level set to: {}", log_level);
+    debug!("Arguments: {:?}", env::args().collect::<Vec<_>>());
+
+    let verbosity = match matches.occurrences_of(ARG_QUIET) {
+        0 => Verbosity::Normal,
+        1 => Verbosity::Quiet,
+        _ => Verbosity::Silent,
+    };
+
+    let reporter: Box<dyn Reporter> =
+        match (matches.value_of(ARG_MESSAGE_FORMAT), CiProvider::detect()) {
+            // Commands that print their results on the standard output must
+            // not have it mixed with events.
+            (Some(MESSAGE_FORMAT_JSON), _) => match matches.subcommand_name() {
+                Some(SUB_COMMAND_HASH | SUB_COMMAND_LIST | SUB_COMMAND_MATRIX) => {
+                    Box::new(JsonReporter::stderr())
+                }
+                _ => Box::new(JsonReporter::stdout()),
+            },
+            (Some(MESSAGE_FORMAT_AUTO), Some(provider)) => {
+                debug!("Running under {:?}: using its message format", provider);
+
+                Box::new(CiReporter::new(provider, verbosity))
+            }
+            _ => Box::new(HumanReporter::new(verbosity)),
+        };
+
+    reporter::set_reporter(reporter);
+
+    let result = make_context(&matches).and_then(|context| {
+        let result = run_subcommand(&context, &matches);
+
+        // The cache only speeds up subsequent runs: failing to save it must
+        // not fail this one.
+        if let Err(err) = context.save_hash_cache() {
+            warn!("Failed to save the hash cache: {}", err);
+        }
 
-    let context = make_context(&matches)?;
+        result
+    });
 
+    if let Err(err) = &result {
+        reporter::error(err);
+    }
+
+    reporter::summary(result.is_ok());
+
+    result
+}
+
+fn run_subcommand(context: &Context, matches: &ArgMatches<'_>) -> Result<()> {
     match matches.subcommand() {
         (SUB_COMMAND_HASH, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            if let Some(package_name) = sub_matches.value_of(ARG_EXPLAIN) {
+                let package = context.resolve_package_by_name(package_name)?;
+                let changes = package.explain_hash()?;
 
-            for package in packages {
-                println!("{}={}", package.name(), package.hash()?);
+                if changes.is_empty() {
+                    println!(
+                        "{}: no changes since version {} was tagged",
+                        package.name(),
+                        package.version()
+                    );
+                }
+
+                for change in changes {
+                    println!("{}: {}", package.name(), change);
+                }
+
+                return Ok(());
             }
 
-            Ok(())
+            let packages = select_packages(context, sub_matches)?;
+
+            if sub_matches.is_present(ARG_BUILD_CONFIG) {
+                for package in packages {
+                    for input in package.build_config_inputs() {
+                        println!("{}: {}", package.name(), input);
+                    }
+                }
+
+                return Ok(());
+            }
+
+            context.compute_hashes(&packages)?;
+
+            match sub_matches.value_of(ARG_FORMAT).unwrap() {
+                FORMAT_TEXT => {
+                    for package in packages {
+                        println!("{}={}", package.name(), package.hash()?);
+                    }
+
+                    Ok(())
+                }
+                format => print_package_records(&packages, format),
+            }
         }
         (SUB_COMMAND_LIST, Some(sub_matches)) => {
-            let packages = match sub_matches.value_of(ARG_CHANGED_SINCE_GIT_REF) {
-                Some(git_ref) => context.resolve_changed_packages(git_ref)?,
-                None => context.packages()?,
-            };
+            let format = sub_matches.value_of(ARG_FORMAT).unwrap();
 
-            for package in packages {
-                println!("{}", package.name());
+            if sub_matches.is_present(ARG_WHY) {
+                if git_changes(sub_matches)?.is_none() {
+                    return Err(
+                        Error::new("no changes to explain").with_explanation(format!(
+                            "`--{ARG_WHY}` requires selecting packages with `--{ARG_CHANGED_SINCE_GIT_REF}` or `--{ARG_CHANGED_SINCE_MERGE_BASE}`."
+                        )),
+                    );
+                }
+
+                // Only explain the packages that the other criteria select.
+                let explanations: Vec<_> = package_selection(sub_matches)?
+                    .resolve_explained(context)?
+                    .into_iter()
+                    .filter_map(|(_, explanation)| explanation)
+                    .collect();
+
+                match format {
+                    FORMAT_TEXT => {
+                        for explanation in explanations {
+                            println!("{explanation}");
+                        }
+                    }
+                    FORMAT_TSV => {
+                        for explanation in explanations {
+                            println!(
+                                "{}\t{}\t{}",
+                                explanation.package,
+                                explanation.dependency_path.join(","),
+                                explanation.changes.iter().join(", ")
+                            );
+                        }
+                    }
+                    format => print_serialized(&explanations, format),
+                }
+
+                return Ok(());
+            }
+
+            let packages = select_packages(context, sub_matches)?;
+
+            if sub_matches.is_present(ARG_BY_GROUP) {
+                list_packages_by_group(&packages, format);
+
+                return Ok(());
+            }
+
+            match format {
+                FORMAT_TEXT => {
+                    for package in packages {
+                        println!("{}", package.name());
+                    }
+
+                    Ok(())
+                }
+                format => {
+                    context.compute_hashes(&packages)?;
+
+                    print_package_records(&packages, format)
+                }
+            }
+        }
+        (SUB_COMMAND_MATRIX, Some(sub_matches)) => {
+            let packages = select_packages(context, sub_matches)?;
+            let entries = MatrixEntry::from_packages(&packages);
+
+            match sub_matches.value_of(ARG_FORMAT).unwrap() {
+                FORMAT_GITLAB => print!(
+                    "{}",
+                    gitlab_pipeline(&entries, sub_matches.value_of(ARG_GITLAB_INCLUDE))?
+                ),
+                _ => println!("{}", github_matrix(&entries)),
             }
 
             Ok(())
         }
         (SUB_COMMAND_BUILD_DIST, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
 
             for package in packages {
                 package.build_dist_targets()?;
@@ -438,7 +925,9 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_PUBLISH_DIST, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
+
+            context.compute_hashes(&packages)?;
 
             for package in packages {
                 package.publish_dist_targets()?;
@@ -447,7 +936,7 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_BUILD, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
 
             let args: Vec<&str> = vec!["cargo", "build"]
                 .into_iter()
@@ -465,7 +954,7 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_TEST, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
 
             let args: Vec<&str> = vec!["cargo", "test"]
                 .into_iter()
@@ -483,7 +972,7 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_CLIPPY, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
 
             let args: Vec<&str> = vec!["cargo", "clippy"]
                 .into_iter()
@@ -501,7 +990,7 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_EXEC, Some(sub_matches)) => {
-            let packages = select_packages(&context, sub_matches)?;
+            let packages = select_packages(context, sub_matches)?;
 
             let args: Vec<&str> = sub_matches.values_of(ARG_COMMAND).unwrap().collect();
 
@@ -517,6 +1006,17 @@ fn run() -> Result<()> {
 
             package.tag()
         }
+        (SUB_COMMAND_MIGRATE_TAGS, Some(sub_matches)) => {
+            let packages = select_packages(context, sub_matches)?;
+
+            context.compute_hashes(&packages)?;
+
+            for package in packages {
+                package.migrate_tag()?;
+            }
+
+            Ok(())
+        }
         (cmd, _) => Err(
             Error::new("Unknown subcommand specified").with_explanation(format!(
                 "Please specify a valid subcommand: `{}` is not a valid subcommand",
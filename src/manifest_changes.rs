@@ -0,0 +1,162 @@
+//! Detection of the packages affected by changes to the Cargo manifests and to
+//! the lockfile.
+//!
+//! Manifests are not part of the package sources, as most of their content -
+//! like tags - does not affect the build. Instead, the old and new versions of
+//! each changed manifest are parsed and compared, and so is the lockfile.
+
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use log::debug;
+
+use crate::{
+    change_explanation::DirectChange, git::GitChanges, lockfile::Lockfile, Package, Result,
+};
+
+/// The manifest tables that only apply at the workspace level.
+///
+/// Changes to `[patch]` and `[replace]` are reflected in the lockfile, and
+/// changes to `[profile]` affect every package.
+const WORKSPACE_TABLES: &[&str] = &["workspace", "profile", "patch", "replace"];
+
+/// Get the packages affected by changes to the manifests or to the lockfile,
+/// along with the change that affects them.
+///
+/// A package is affected if its manifest changed - ignoring its tags - or if
+/// its resolved dependency closure changed in the lockfile. Changes to the
+/// workspace profiles affect all packages.
+pub(crate) fn changed_packages<'a, 'g>(
+    repo: &Repository,
+    changes: &GitChanges,
+    changed_files: &[PathBuf],
+    workspace_root: &Path,
+    packages: &'a [Package<'g>],
+) -> Result<Vec<(&'a Package<'g>, DirectChange)>> {
+    let is_changed = |path: &Path| {
+        changed_files
+            .iter()
+            .any(|changed_file| changed_file == path)
+    };
+
+    let root_manifest_path = workspace_root.join("Cargo.toml");
+
+    if is_changed(&root_manifest_path) {
+        let (old, new) = changes.file_versions(repo, &root_manifest_path)?;
+        let profiles =
+            |data| parse_manifest(data).and_then(|manifest| manifest.get("profile").cloned());
+
+        if profiles(old) != profiles(new) {
+            debug!("Workspace profiles changed: all packages are affected");
+
+            return Ok(packages
+                .iter()
+                .map(|package| (package, DirectChange::Profiles))
+                .collect());
+        }
+    }
+
+    let mut result = Vec::new();
+
+    for package in packages {
+        if is_changed(package.manifest_path()) {
+            let (old, new) = changes.file_versions(repo, package.manifest_path())?;
+
+            if package_manifest(old) != package_manifest(new) {
+                debug!("Manifest of {} changed", package.name());
+
+                result.push((package, DirectChange::Manifest));
+            }
+        }
+    }
+
+    let lockfile_path = workspace_root.join("Cargo.lock");
+
+    if is_changed(&lockfile_path) {
+        let (old, new) = changes.file_versions(repo, &lockfile_path)?;
+        let (old, new) = (parse_lockfile(old), parse_lockfile(new));
+
+        for package in packages {
+            if old.dependency_closure(package.name()) != new.dependency_closure(package.name()) {
+                debug!("Resolved dependencies of {} changed", package.name());
+
+                result.push((package, DirectChange::Lockfile));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a manifest, treating an invalid manifest like a missing one.
+fn parse_manifest(data: Option<String>) -> Option<toml::Value> {
+    toml::from_str(&data?)
+        .map_err(|err| debug!("Ignoring invalid manifest: {}", err))
+        .ok()
+}
+
+/// Parse a manifest, keeping only the parts that affect the package.
+fn package_manifest(data: Option<String>) -> Option<toml::Value> {
+    let mut manifest = parse_manifest(data)?;
+
+    if let Some(manifest) = manifest.as_table_mut() {
+        for table in WORKSPACE_TABLES {
+            manifest.remove(*table);
+        }
+    }
+
+    if let Some(monorepo) = manifest
+        .get_mut("package")
+        .and_then(|package| package.get_mut("metadata"))
+        .and_then(|metadata| metadata.get_mut("monorepo"))
+        .and_then(toml::Value::as_table_mut)
+    {
+        monorepo.remove("tags");
+    }
+
+    Some(manifest)
+}
+
+/// Parse a lockfile, treating an invalid lockfile like a missing one.
+fn parse_lockfile(data: Option<String>) -> Lockfile {
+    data.and_then(|data| {
+        Lockfile::parse(&data)
+            .map_err(|err| debug!("Ignoring invalid lockfile: {}", err.description()))
+            .ok()
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_manifest() {
+        let manifest = |version: &str, tags: &str, profile: &str| {
+            package_manifest(Some(format!(
+                r#"
+[package]
+name = "mybinary"
+version = "{version}"
+
+[package.metadata.monorepo.tags]
+{tags}
+
+[profile.release]
+{profile}
+"#
+            )))
+        };
+
+        let base = manifest("0.1.0", "", "");
+
+        assert_eq!(
+            base,
+            manifest("0.1.0", r#""0.1.0" = "sha256-v6:abcdef""#, "")
+        );
+        assert_eq!(base, manifest("0.1.0", "", "lto = true"));
+        assert_ne!(base, manifest("0.1.1", "", ""));
+        assert_ne!(base, None);
+    }
+}
@@ -0,0 +1,156 @@
+//! Generation of CI job matrices from distribution targets.
+
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::{Error, Package, Result};
+
+/// The name of the hidden job the GitLab jobs extend.
+pub const GITLAB_JOB_TEMPLATE: &str = ".monorepo-dist-target";
+
+/// An entry of a CI job matrix: a distribution target of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatrixEntry {
+    pub package: String,
+    pub version: String,
+    pub dist_target: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub runtime: String,
+}
+
+impl MatrixEntry {
+    /// Get one entry per distribution target of the specified packages.
+    pub fn from_packages(packages: &[Package<'_>]) -> Vec<Self> {
+        packages
+            .iter()
+            .flat_map(|package| {
+                package
+                    .monorepo_metadata()
+                    .dist_targets
+                    .iter()
+                    .map(move |(name, dist_target)| Self {
+                        package: package.name().to_string(),
+                        version: package.version().to_string(),
+                        dist_target: name.clone(),
+                        type_name: dist_target.type_name().to_string(),
+                        runtime: dist_target.target_runtime().to_string(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Render the entries as a GitHub Actions `strategy.matrix`, on a single line
+/// so that it can be set as a step output.
+///
+/// An empty list of entries gives an empty `include` list: jobs that use the
+/// matrix should be skipped in that case, as GitHub Actions rejects empty
+/// matrices.
+pub fn github_matrix(entries: &[MatrixEntry]) -> String {
+    #[derive(Serialize)]
+    struct Matrix<'a> {
+        include: &'a [MatrixEntry],
+    }
+
+    serde_json::to_string(&Matrix { include: entries }).unwrap()
+}
+
+/// Render the entries as a GitLab child pipeline, with one job per entry.
+///
+/// Jobs extend the [`GITLAB_JOB_TEMPLATE`] hidden job, which is expected to be
+/// defined in the specified local include file, and receive the fields of
+/// their entry as `MONOREPO_*` variables. As GitLab rejects pipelines without
+/// jobs, an empty list of entries gives a pipeline with a single job that does
+/// nothing.
+pub fn gitlab_pipeline(entries: &[MatrixEntry], include: Option<&str>) -> Result<String> {
+    let mut pipeline = Mapping::new();
+
+    if entries.is_empty() {
+        let mut job = Mapping::new();
+        job.insert(
+            "script".into(),
+            Value::Sequence(vec!["echo 'No distribution targets to process.'".into()]),
+        );
+        pipeline.insert("nothing-to-do".into(), job.into());
+    } else {
+        if let Some(include) = include {
+            let mut local = Mapping::new();
+            local.insert("local".into(), include.into());
+            pipeline.insert("include".into(), Value::Sequence(vec![local.into()]));
+        }
+
+        for entry in entries {
+            let mut variables = Mapping::new();
+
+            for (name, value) in [
+                ("MONOREPO_PACKAGE", &entry.package),
+                ("MONOREPO_PACKAGE_VERSION", &entry.version),
+                ("MONOREPO_DIST_TARGET", &entry.dist_target),
+                ("MONOREPO_DIST_TARGET_TYPE", &entry.type_name),
+                ("MONOREPO_DIST_TARGET_RUNTIME", &entry.runtime),
+            ] {
+                variables.insert(name.into(), value.as_str().into());
+            }
+
+            let mut job = Mapping::new();
+            job.insert("extends".into(), GITLAB_JOB_TEMPLATE.into());
+            job.insert("variables".into(), variables.into());
+
+            pipeline.insert(
+                format!("{}: {}", entry.package, entry.dist_target).into(),
+                job.into(),
+            );
+        }
+    }
+
+    serde_yaml::to_string(&pipeline)
+        .map_err(|err| Error::new("failed to serialize GitLab pipeline").with_source(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> MatrixEntry {
+        MatrixEntry {
+            package: "service".to_string(),
+            version: "0.1.0".to_string(),
+            dist_target: "service-image".to_string(),
+            type_name: "docker".to_string(),
+            runtime: "x86_64-unknown-linux-gnu".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_github_matrix() {
+        assert_eq!(github_matrix(&[]), r#"{"include":[]}"#);
+        assert_eq!(
+            github_matrix(&[entry()]),
+            r#"{"include":[{"package":"service","version":"0.1.0","dist_target":"service-image","type":"docker","runtime":"x86_64-unknown-linux-gnu"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_gitlab_pipeline() {
+        let pipeline: Value =
+            serde_yaml::from_str(&gitlab_pipeline(&[], Some("ci/dist.yml")).unwrap()).unwrap();
+
+        assert_eq!(pipeline.as_mapping().unwrap().len(), 1);
+        assert!(pipeline["nothing-to-do"]["script"].is_sequence());
+
+        let pipeline: Value =
+            serde_yaml::from_str(&gitlab_pipeline(&[entry()], Some("ci/dist.yml")).unwrap())
+                .unwrap();
+
+        assert_eq!(pipeline["include"][0]["local"], Value::from("ci/dist.yml"));
+
+        let job = &pipeline["service: service-image"];
+
+        assert_eq!(job["extends"], Value::from(GITLAB_JOB_TEMPLATE));
+        assert_eq!(
+            job["variables"]["MONOREPO_DIST_TARGET_TYPE"],
+            Value::from("docker")
+        );
+    }
+}
@@ -1,17 +1,18 @@
 //! Metadata structures for the various targets.
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
     path::{Path, PathBuf},
 };
 
+use itertools::Itertools;
 use log::debug;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    aws_lambda::AwsLambdaMetadata, dist_target::DistTarget, docker::DockerMetadata, Error,
-    ErrorContext, Package, Result,
+    aws_lambda::AwsLambdaMetadata, dist_target::DistTarget, docker::DockerMetadata,
+    hash::HashAlgorithm, Error, ErrorContext, Package, Result,
 };
 
 /// The root metadata structure.
@@ -21,6 +22,14 @@ pub(crate) struct Metadata {
     pub dist_targets: BTreeMap<String, DistTargetMetadata>,
     #[serde(default)]
     pub tags: BTreeMap<semver::Version, String>,
+    #[serde(default)]
+    pub deps_hash: Option<String>,
+    #[serde(default)]
+    pub hash_include: Vec<String>,
+    #[serde(default)]
+    pub hash_exclude: Vec<String>,
+    #[serde(default)]
+    pub groups: BTreeSet<String>,
 }
 
 impl Metadata {
@@ -56,6 +65,162 @@ impl Metadata {
     }
 }
 
+/// The workspace-level metadata structure.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WorkspaceMetadata {
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Whether CRLF line endings of text files are normalized to LF before
+    /// being digested, so that hashes agree between checkouts that use
+    /// different line endings.
+    #[serde(default)]
+    pub normalize_line_endings: bool,
+    /// Whether the files listed in the dep-info files of the last build are
+    /// part of the sources of the packages.
+    #[serde(default)]
+    pub dep_info_sources: bool,
+    /// Glob patterns, relative to the workspace root, of files that mark
+    /// packages as changed when they change.
+    #[serde(default)]
+    pub triggers: BTreeMap<String, PackageSelector>,
+}
+
+/// A selection of workspace packages, as used in the workspace metadata.
+///
+/// It is written either `"all"`, as the name or list of names of the selected
+/// packages, or as a table with `packages` and `groups` lists.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawPackageSelector")]
+pub(crate) enum PackageSelector {
+    All,
+    Some {
+        packages: Vec<String>,
+        groups: Vec<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPackageSelector {
+    One(String),
+    Many(Vec<String>),
+    Table(RawPackageSelectorTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPackageSelectorTable {
+    #[serde(default)]
+    packages: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+impl TryFrom<RawPackageSelector> for PackageSelector {
+    type Error = String;
+
+    fn try_from(raw: RawPackageSelector) -> std::result::Result<Self, Self::Error> {
+        let (packages, groups) = match raw {
+            RawPackageSelector::One(selector) if selector == "all" => return Ok(Self::All),
+            RawPackageSelector::One(name) => (vec![name], vec![]),
+            RawPackageSelector::Many(names) => (names, vec![]),
+            RawPackageSelector::Table(table) => (table.packages, table.groups),
+        };
+
+        if packages.is_empty() && groups.is_empty() {
+            return Err("a package selection cannot be empty".to_string());
+        }
+
+        Ok(Self::Some { packages, groups })
+    }
+}
+
+impl PackageSelector {
+    /// Select packages among the specified workspace packages.
+    pub(crate) fn select<'a, 'g>(
+        &self,
+        packages: &'a [Package<'g>],
+    ) -> Result<Vec<&'a Package<'g>>> {
+        match self {
+            Self::All => Ok(packages.iter().collect()),
+            Self::Some {
+                packages: names,
+                groups,
+            } => {
+                let mut selected = names
+                    .iter()
+                    .map(|name| {
+                        packages
+                            .iter()
+                            .find(|package| package.name() == name)
+                            .ok_or_else(|| {
+                                Error::new("package not found").with_explanation(format!(
+                                    "The workspace metadata references a package named `{name}`, which is not part of the workspace."
+                                ))
+                            })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                for group in groups {
+                    let len = selected.len();
+
+                    selected.extend(packages.iter().filter(|package| package.in_group(group)));
+
+                    if selected.len() == len {
+                        return Err(Error::new("group not found").with_explanation(format!(
+                            "The workspace metadata references a group named `{group}`, which no package of the workspace belongs to."
+                        )));
+                    }
+                }
+
+                Ok(selected
+                    .into_iter()
+                    .unique_by(|package| package.id())
+                    .collect())
+            }
+        }
+    }
+}
+
+impl WorkspaceMetadata {
+    /// Parse the `workspace.metadata` table, as output by `cargo metadata`.
+    ///
+    /// `guppy` does not expose that table, so it is read from the raw output.
+    pub(crate) fn new(cargo_metadata: &guppy::CargoMetadata) -> Result<Self> {
+        #[derive(Debug, Deserialize)]
+        struct RawMetadata {
+            workspace_root: PathBuf,
+            #[serde(rename = "metadata", default)]
+            workspace_metadata: serde_json::Value,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct RootMetadata {
+            #[serde(default)]
+            monorepo: WorkspaceMetadata,
+        }
+
+        let raw: RawMetadata = serde_json::to_value(cargo_metadata)
+            .and_then(serde_json::from_value)
+            .map_err(|err| Error::new("failed to read Cargo metadata").with_source(err))?;
+
+        let metadata: Option<RootMetadata> =
+            serde_path_to_error::deserialize(raw.workspace_metadata).map_err(|err| {
+                Error::new("failed to parse workspace metadata")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "failed to parse the Cargo metadata for the workspace at {}",
+                        raw.workspace_root.display()
+                    ))
+            })?;
+
+        Ok(metadata
+            .map(|metadata| metadata.monorepo)
+            .unwrap_or_default())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum DistTargetMetadata {
     Docker(DockerMetadata),
@@ -63,6 +228,23 @@ pub(crate) enum DistTargetMetadata {
 }
 
 impl DistTargetMetadata {
+    /// Get the type of the distribution target, as written in the metadata.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Self::Docker(_) => "docker",
+            Self::AwsLambda(_) => "aws-lambda",
+        }
+    }
+
+    /// Get the target runtime the binaries of the distribution target are
+    /// built for.
+    pub(crate) fn target_runtime(&self) -> &str {
+        match self {
+            Self::Docker(docker) => &docker.target_runtime,
+            Self::AwsLambda(lambda) => &lambda.target_runtime,
+        }
+    }
+
     pub(crate) fn to_dist_target<'g>(
         &self,
         name: String,
@@ -265,3 +447,83 @@ impl Serialize for Template {
         serializer.serialize_str(&self.source)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PackageSelector;
+    use crate::{test_workspace::TestWorkspace, HashAlgorithm};
+
+    #[test]
+    fn test_workspace_metadata() {
+        let workspace = TestWorkspace::new("workspace-metadata");
+        workspace
+            .write(
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"packages/*\"]\nresolver = \"2\"\n\n\
+                 [workspace.metadata.monorepo]\nhash_algorithm = \"blake3\"\n\
+                 normalize_line_endings = true\n",
+            )
+            .add_package("api", &[], "");
+
+        let context = workspace.context();
+        let metadata = context.workspace_metadata();
+
+        assert_eq!(metadata.hash_algorithm, HashAlgorithm::Blake3);
+        assert!(metadata.normalize_line_endings);
+        assert!(!metadata.dep_info_sources);
+    }
+
+    #[test]
+    fn test_package_selector() {
+        let parse = |toml: &str| {
+            toml::from_str::<std::collections::BTreeMap<String, PackageSelector>>(toml)
+                .map(|mut selectors| selectors.remove("selector").unwrap())
+        };
+        let strings = |values: &[&str]| values.iter().map(ToString::to_string).collect();
+
+        assert_eq!(parse("selector = \"all\"").unwrap(), PackageSelector::All);
+        assert_eq!(
+            parse("selector = \"api\"").unwrap(),
+            PackageSelector::Some {
+                packages: strings(&["api"]),
+                groups: vec![],
+            }
+        );
+        assert_eq!(
+            parse("selector = { packages = [\"api\"], groups = [\"frontend\"] }").unwrap(),
+            PackageSelector::Some {
+                packages: strings(&["api"]),
+                groups: strings(&["frontend"]),
+            }
+        );
+        assert!(parse("selector = []").is_err());
+        assert!(parse("selector = {}").is_err());
+        assert!(parse("selector = { group = [\"frontend\"] }").is_err());
+
+        let workspace = TestWorkspace::new("package-selector");
+        workspace
+            .add_package("api", &[], "groups = [\"backend\"]")
+            .add_package("worker", &[], "groups = [\"backend\", \"jobs\"]")
+            .add_package("web", &[], "groups = [\"frontend\"]");
+        let context = workspace.context();
+        let packages = context.packages().unwrap();
+
+        let select = |toml: &str| {
+            parse(toml).unwrap().select(&packages).map(|selected| {
+                selected
+                    .into_iter()
+                    .map(|package| package.name().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // Packages selected both by name and by group are only listed once.
+        let mut selected =
+            select("selector = { packages = [\"api\"], groups = [\"backend\"] }").unwrap();
+        selected.sort();
+        assert_eq!(selected, ["api", "worker"]);
+
+        assert!(select("selector = { groups = [\"unknown\"] }").is_err());
+        assert!(select("selector = \"unknown\"").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeSet,
     ffi::OsStr,
     io::{Read, Seek, Write},
     path::Path,
@@ -6,10 +7,20 @@ use std::{
 };
 
 use itertools::Itertools;
+use log::debug;
 
 use crate::{
-    action_step, hash::HashSource, ignore_step, metadata::Metadata, sources::Sources, Context,
-    Error, Result,
+    action_step,
+    build_config::BuildConfig,
+    hash::{HashAlgorithm, HashFormat, HashSource},
+    hash_manifest::{HashChange, HashManifests},
+    ignore_step,
+    metadata::Metadata,
+    package_record::PackageRecord,
+    reporter::{self, Operation, Subject},
+    run_log,
+    sources::Sources,
+    Context, Error, Result,
 };
 
 /// A package in the workspace.
@@ -19,26 +30,51 @@ pub struct Package<'g> {
     package_metadata: guppy::graph::PackageMetadata<'g>,
     monorepo_metadata: Metadata,
     sources: Sources,
+    build_config: BuildConfig,
+    hash_algorithm: HashAlgorithm,
 }
 
 impl<'g> Package<'g> {
     pub(crate) fn new(
         context: &'g Context,
         package_metadata: guppy::graph::PackageMetadata<'g>,
+    ) -> Result<Self> {
+        Self::with_hash_algorithm(context, package_metadata, context.hash_algorithm())
+    }
+
+    /// Load a package whose files are digested, and whose hash is computed,
+    /// with the specified algorithm rather than the one of the context.
+    pub(crate) fn with_hash_algorithm(
+        context: &'g Context,
+        package_metadata: guppy::graph::PackageMetadata<'g>,
+        hash_algorithm: HashAlgorithm,
     ) -> Result<Self> {
         assert!(
             package_metadata.in_workspace(),
             "cannot build a Package instance from a non-workspace package"
         );
 
+        let digest_options = context.digest_options(hash_algorithm);
         let monorepo_metadata = Metadata::new(&package_metadata)?;
-        let sources = Sources::from_package(context, &package_metadata)?;
+        let sources = Sources::from_package(
+            context,
+            &package_metadata,
+            &monorepo_metadata,
+            digest_options,
+        )?;
+        let build_config = BuildConfig::new(
+            context.workspace_root(),
+            package_metadata.manifest_path().as_std_path(),
+            digest_options,
+        )?;
 
         Ok(Self {
             context,
             package_metadata,
             monorepo_metadata,
             sources,
+            build_config,
+            hash_algorithm,
         })
     }
 
@@ -46,6 +82,12 @@ impl<'g> Package<'g> {
         self.context
     }
 
+    /// Get the algorithm the files and the hash of the package are computed
+    /// with.
+    pub(crate) fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
     pub(crate) fn package_metadata(&self) -> &guppy::graph::PackageMetadata<'g> {
         &self.package_metadata
     }
@@ -73,22 +115,63 @@ impl<'g> Package<'g> {
             .collect()
     }
 
+    /// Get all the workspace packages that depend on this package, directly or
+    /// not, in dependency order.
     pub fn dependant_packages(&self) -> Result<Vec<Package<'g>>> {
-        self.directly_dependant_packages()?
+        self.context
+            .dependant_package_ids(std::iter::once(self.id()))?
             .into_iter()
-            .map(|package| {
-                package
-                    .directly_dependant_packages()
-                    .map(|packages| std::iter::once(package).chain(packages.into_iter()))
-            })
-            .collect::<Result<Vec<_>>>()
-            .map(|packages| packages.into_iter().flatten().collect())
+            .filter(|id| *id != self.id())
+            .map(|id| Package::new(self.context, self.context.package_metadata(id)?))
+            .collect()
     }
 
     pub fn sources(&self) -> &Sources {
         &self.sources
     }
 
+    pub(crate) fn build_config(&self) -> &BuildConfig {
+        &self.build_config
+    }
+
+    /// Get a description of the build configuration inputs that take part in
+    /// the hash of the package.
+    pub fn build_config_inputs(&self) -> Vec<String> {
+        self.build_config
+            .inputs()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Get the groups the package belongs to.
+    pub fn groups(&self) -> &BTreeSet<String> {
+        &self.monorepo_metadata.groups
+    }
+
+    pub fn in_group(&self, group: &str) -> bool {
+        self.monorepo_metadata.groups.contains(group)
+    }
+
+    /// Check whether the package has a distribution target of the specified
+    /// type, like `docker` or `aws-lambda`.
+    pub fn has_dist_target_type(&self, type_name: &str) -> bool {
+        self.monorepo_metadata
+            .dist_targets
+            .values()
+            .any(|dist_target| dist_target.type_name() == type_name)
+    }
+
+    /// Get a machine-readable description of the package, including its hash
+    /// and the destinations of its distribution targets.
+    pub fn record(&self) -> Result<PackageRecord> {
+        PackageRecord::new(self)
+    }
+
+    pub fn manifest_path(&self) -> &Path {
+        self.package_metadata.manifest_path().as_std_path()
+    }
+
     pub fn root(&self) -> &Path {
         self.package_metadata
             .manifest_path()
@@ -98,36 +181,48 @@ impl<'g> Package<'g> {
     }
 
     pub fn build_dist_targets(&self) -> Result<()> {
-        for dist_target in self.monorepo_metadata.dist_targets(self) {
-            action_step!("Building", "distribution {}", dist_target);
-            let before = std::time::Instant::now();
-            dist_target.build()?;
-            let duration = before.elapsed();
-            action_step!("Finished", "distribution in {:.2}s", duration.as_secs_f64());
-        }
+        reporter::operation(Subject::package(self), Operation::Build, || {
+            self.check_dependencies_hash()?;
 
-        Ok(())
+            for dist_target in self.monorepo_metadata.dist_targets(self) {
+                reporter::operation(dist_target.subject(), Operation::Build, || {
+                    dist_target.build()
+                })?;
+            }
+
+            Ok(())
+        })
     }
 
     pub fn publish_dist_targets(&self) -> Result<()> {
-        if !self.tag_matches()? {
-            ignore_step!(
-                "Skipping",
-                "publication as current hash does not match the registered one for this version"
-            );
+        reporter::operation(Subject::package(self), Operation::Publish, || {
+            self.check_dependencies_hash()?;
+
+            if !self.tag_matches()? {
+                if let Some(format) = self.outdated_tag_format() {
+                    ignore_step!(
+                        "Skipping",
+                        "publication as the registered hash for this version uses the outdated format `{}`: migrate the tags of the package",
+                        format,
+                    );
+                } else {
+                    ignore_step!(
+                        "Skipping",
+                        "publication as current hash does not match the registered one for this version"
+                    );
+                }
 
-            return Ok(());
-        }
+                return Ok(());
+            }
 
-        for dist_target in self.monorepo_metadata.dist_targets(self) {
-            action_step!("Publishing", "distribution {}", dist_target);
-            let before = std::time::Instant::now();
-            dist_target.publish()?;
-            let duration = before.elapsed();
-            action_step!("Finished", "publication in {:.2}s", duration.as_secs_f64());
-        }
+            for dist_target in self.monorepo_metadata.dist_targets(self) {
+                reporter::operation(dist_target.subject(), Operation::Publish, || {
+                    dist_target.publish()
+                })?;
+            }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn execute(
@@ -140,26 +235,65 @@ impl<'g> Package<'g> {
             return Err(Error::new("no arguments provided to execute"));
         }
 
-        action_step!("Executing", "{}", self.package_metadata.id());
-        action_step!(
-            "Running",
-            "`{}`",
-            args.iter().map(|s| s.as_ref().to_string_lossy()).join(" "),
-        );
+        reporter::operation(Subject::package(self), Operation::Execute, || {
+            action_step!("Executing", "{}", self.package_metadata.id());
+            reporter::command(args.iter().map(|s| s.as_ref().to_string_lossy()).join(" "));
 
-        let program = args[0].as_ref();
-        let program_args = &args[1..];
-        let mut cmd = Command::new(program);
+            let program = args[0].as_ref();
+            let program_args = &args[1..];
+            let mut cmd = Command::new(program);
 
-        cmd.args(program_args)
-            .current_dir(&self.package_metadata.manifest_path().parent().unwrap());
+            cmd.args(program_args)
+                .current_dir(self.package_metadata.manifest_path().parent().unwrap());
 
-        cmd.status()
-            .map_err(|err| Error::new("failed to execute command").with_source(err))
+            run_log::run_command(&mut cmd, true)
+                .map(|output| output.status)
+                .map_err(|err| Error::new("failed to execute command").with_source(err))
+        })
     }
 
+    /// Get the hash of the package.
+    ///
+    /// The hash is cached in the context, along with the hashes of all the
+    /// workspace packages it depends on.
     pub fn hash(&self) -> Result<String> {
-        Ok(HashSource::new(self)?.hash())
+        self.context.package_hash(self)
+    }
+
+    /// Get the hash of the dependencies of the package.
+    pub fn dependencies_hash(&self) -> Result<String> {
+        Ok(HashSource::new(self)?.dependencies_hash())
+    }
+
+    /// Check that the dependencies hash specified in the manifest, if any,
+    /// matches the current one.
+    pub fn check_dependencies_hash(&self) -> Result<()> {
+        if let Some(expected_hash) = &self.monorepo_metadata.deps_hash {
+            let hash = self.dependencies_hash()?;
+
+            if expected_hash != &hash {
+                if let Some(format) = HashFormat::parse(expected_hash)
+                    .filter(|format| format != &self.context.hash_format())
+                {
+                    return Err(Error::new("dependencies hash format mismatch").with_explanation(format!(
+                        "The dependencies hash of `{}` was recorded with the hash format `{}` but the current format is `{}`. Set `deps_hash = \"{}\"` in the `[package.metadata.monorepo]` section of its manifest.",
+                        self.name(),
+                        format,
+                        self.context.hash_format(),
+                        hash,
+                    )));
+                }
+
+                return Err(Error::new("dependencies hash mismatch").with_explanation(format!(
+                    "The dependencies of `{}` changed since its dependencies hash was recorded. A version bump may be necessary: once done, set `deps_hash = \"{}\"` in the `[package.metadata.monorepo]` section of its manifest.",
+                    self.name(),
+                    hash,
+                ))
+                .with_output(format!("expected: {expected_hash}\ncurrent: {hash}")));
+            }
+        }
+
+        Ok(())
     }
 
     pub fn get_tag(&self, version: &semver::Version) -> Option<&String> {
@@ -167,21 +301,81 @@ impl<'g> Package<'g> {
     }
 
     /// Check that the current tag matches the current hash.
+    ///
+    /// Tags recorded with a different hash algorithm are compared to the hash
+    /// of the package computed with that algorithm, as long as their format
+    /// version is the current one.
     pub fn tag_matches(&self) -> Result<bool> {
         let version = self.version();
         let hash = self.hash()?;
 
         if let Some(current_hash) = self.get_tag(version) {
-            return Ok(current_hash == &hash);
+            if current_hash == &hash {
+                return Ok(true);
+            }
+
+            if let Some(format) = HashFormat::parse(current_hash) {
+                if !format.is_outdated() && format.algorithm != self.context.hash_algorithm() {
+                    debug!(
+                        "Tag of {} uses the hash algorithm `{}`: computing its hash with that algorithm",
+                        self.id(),
+                        format.algorithm,
+                    );
+
+                    let hash = Self::with_hash_algorithm(
+                        self.context,
+                        self.package_metadata,
+                        format.algorithm,
+                    )?
+                    .hash()?;
+
+                    return Ok(current_hash == &hash);
+                }
+            }
         }
 
         Ok(false)
     }
 
+    /// Get the format of the tag registered for the current version, if that
+    /// format is outdated.
+    ///
+    /// Such a tag cannot be compared to the current hash and must be migrated
+    /// by re-tagging the package.
+    pub fn outdated_tag_format(&self) -> Option<HashFormat> {
+        self.get_tag(self.version())
+            .and_then(|hash| HashFormat::parse(hash))
+            .filter(HashFormat::is_outdated)
+    }
+
+    /// Explain how the current hash differs from the one that was recorded
+    /// when the current version was tagged.
+    pub fn explain_hash(&self) -> Result<Vec<HashChange>> {
+        let version = self.version();
+        let hash_manifests = HashManifests::load(self.root())?;
+
+        let previous = hash_manifests.get(version).ok_or_else(|| {
+            Error::new("no hash manifest recorded for version").with_explanation(format!(
+                "No hash manifest was recorded when tagging version `{}` of `{}`. Tag the package again to record one.",
+                version,
+                self.name(),
+            ))
+        })?;
+
+        Ok(HashSource::new(self)?.manifest().changes_since(previous))
+    }
+
     /// Tag the package with its current version and hash.
     ///
-    /// If a tag already exist for the version, the call will fail.
+    /// If a tag already exist for the version, the call will fail, unless that
+    /// tag matches the current content with another hash algorithm, in which
+    /// case it gets migrated. A tag recorded with an outdated hash format
+    /// version cannot be verified: it is only replaced with `--force`. The
+    /// call also fails if the dependencies hash specified in the manifest does
+    /// not match.
     pub fn tag(&self) -> Result<()> {
+        self.check_dependencies_hash()?;
+
         let version = self.version();
         let hash = self.hash()?;
 
@@ -200,6 +394,24 @@ impl<'g> Package<'g> {
             if self.context.options().force {
                 action_step!("Re-tagging", "{} with hash `{}`", self.id(), &hash);
                 Ok(())
+            } else if let Some(format) = self.outdated_tag_format() {
+                Err(Error::new("tag uses an outdated hash format")
+                    .with_explanation(format!(
+                        "The tag for version `{}` was recorded with the hash format `{}`, which cannot be compared to the current format `{}`: whether the package changed since cannot be verified. If it did not, use `--force` to re-tag it. Otherwise, increment the package version number and try again.",
+                        version,
+                        format,
+                        self.context.hash_format(),
+                    ))
+                )
+            } else if self.tag_matches()? {
+                action_step!(
+                    "Migrating",
+                    "tag of {} to hash format `{}` with hash `{}`",
+                    self.id(),
+                    self.context.hash_format(),
+                    &hash,
+                );
+                Ok(())
             } else {
                 Err(Error::new("tag already exists for version")
                     .with_explanation(format!(
@@ -215,6 +427,94 @@ impl<'g> Package<'g> {
             Ok(())
         }?;
 
+        self.write_tag(version, hash)
+    }
+
+    /// Migrate the tag of the current version of the package to the current
+    /// hash format.
+    ///
+    /// Tags that use a different algorithm are only migrated if they match the
+    /// current content of the package. Tags with an outdated format version
+    /// cannot be verified and are only migrated with `--force`. Tags of
+    /// previous versions cannot be recomputed and are left untouched.
+    pub fn migrate_tag(&self) -> Result<()> {
+        let version = self.version();
+        let hash_format = self.context.hash_format();
+
+        let previous_versions_count = self
+            .monorepo_metadata
+            .tags
+            .iter()
+            .filter(|(tag_version, tag_hash)| {
+                *tag_version != version && HashFormat::parse(tag_hash) != Some(hash_format)
+            })
+            .count();
+
+        if previous_versions_count > 0 {
+            ignore_step!(
+                "Skipping",
+                "{} tag(s) of previous versions of {} as they cannot be recomputed",
+                previous_versions_count,
+                self.name(),
+            );
+        }
+
+        let Some(current_hash) = self.get_tag(version) else {
+            ignore_step!("Skipping", "{} as it is not tagged", self.id());
+
+            return Ok(());
+        };
+
+        let hash = self.hash()?;
+
+        if current_hash == &hash {
+            ignore_step!(
+                "Up-to-date",
+                "tag of {} already uses the hash format `{}`",
+                self.id(),
+                hash_format,
+            );
+
+            return Ok(());
+        }
+
+        if !self.context.options().force {
+            if let Some(format) = self.outdated_tag_format() {
+                ignore_step!(
+                    "Skipping",
+                    "{} as its tag uses the outdated hash format `{}` and cannot be verified: use `--force` to migrate it anyway",
+                    self.id(),
+                    format,
+                );
+
+                return Ok(());
+            }
+        }
+
+        if !self.context.options().force && !self.tag_matches()? {
+            ignore_step!(
+                "Skipping",
+                "{} as its tag does not match its current content: you may need to increment the package version number",
+                self.id(),
+            );
+
+            return Ok(());
+        }
+
+        action_step!(
+            "Migrating",
+            "tag of {} to hash format `{}` with hash `{}`",
+            self.id(),
+            hash_format,
+            &hash,
+        );
+
+        self.write_tag(version, hash)
+    }
+
+    /// Write the tag for the specified version in the manifest, and record the
+    /// associated hash manifest.
+    fn write_tag(&self, version: &semver::Version, hash: String) -> Result<()> {
         let manifest_path = &self.package_metadata.manifest_path();
         let mut manifest_file = std::fs::OpenOptions::new()
             .read(true)
@@ -240,8 +540,195 @@ impl<'g> Package<'g> {
             .seek(std::io::SeekFrom::Start(0))
             .map_err(|err| Error::new("failed to rewind in manifest file").with_source(err))?;
 
+        // The new content can be shorter than the previous one.
+        manifest_file
+            .set_len(0)
+            .map_err(|err| Error::new("failed to truncate manifest").with_source(err))?;
+
         manifest_file
             .write_all(document.to_string().as_bytes())
-            .map_err(|err| Error::new("failed to write manifest").with_source(err))
+            .map_err(|err| Error::new("failed to write manifest").with_source(err))?;
+
+        let mut hash_manifests = HashManifests::load(self.root())?;
+        hash_manifests.insert(version.clone(), HashSource::new(self)?.manifest());
+        hash_manifests.save(self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_workspace::TestWorkspace, HashAlgorithm, Options};
+
+    #[test]
+    fn test_tag_with_outdated_format_requires_force() {
+        let workspace = TestWorkspace::new("tag-outdated");
+        let outdated_tag = format!("sha256:{}", "0".repeat(64));
+        workspace.add_package(
+            "service",
+            &[],
+            &format!("tags = {{ \"0.1.0\" = \"{outdated_tag}\" }}"),
+        );
+
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert!(service.outdated_tag_format().is_some());
+        assert!(service.tag().is_err());
+
+        service.migrate_tag().unwrap();
+        assert_eq!(
+            workspace
+                .context()
+                .resolve_package_by_name("service")
+                .unwrap()
+                .get_tag(service.version()),
+            Some(&outdated_tag)
+        );
+
+        let context = workspace
+            .context_builder()
+            .with_options(Options {
+                force: true,
+                no_cache: true,
+                ..Options::default()
+            })
+            .build()
+            .unwrap();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        service.tag().unwrap();
+
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert!(service.outdated_tag_format().is_none());
+        assert!(service.tag_matches().unwrap());
+    }
+
+    #[test]
+    fn test_retag_with_shorter_hash() {
+        let workspace = TestWorkspace::new("retag-shorter");
+        workspace.add_package(
+            "service",
+            &[],
+            &format!(
+                "tags = {{ \"0.1.0\" = \"{}:{}\" }}",
+                crate::HashFormat::current(HashAlgorithm::Sha256),
+                "0".repeat(256)
+            ),
+        );
+
+        workspace
+            .context_builder()
+            .with_options(Options {
+                force: true,
+                no_cache: true,
+                ..Options::default()
+            })
+            .build()
+            .unwrap()
+            .resolve_package_by_name("service")
+            .unwrap()
+            .tag()
+            .unwrap();
+
+        // The manifest is rewritten entirely, without leftovers of the
+        // previous, longer, tag.
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert!(service.tag_matches().unwrap());
+    }
+
+    #[test]
+    fn test_tag_matches_with_another_algorithm() {
+        let workspace = TestWorkspace::new("tag-algorithm");
+        workspace
+            .add_package("api", &[], "")
+            .add_package("service", &["api"], "");
+
+        let blake3_hash = workspace
+            .context_builder()
+            .with_hash_algorithm(HashAlgorithm::Blake3)
+            .build()
+            .unwrap()
+            .resolve_package_by_name("service")
+            .unwrap()
+            .hash()
+            .unwrap();
+
+        assert!(blake3_hash.starts_with("blake3-v"));
+
+        workspace.add_package(
+            "service",
+            &["api"],
+            &format!("tags = {{ \"0.1.0\" = \"{blake3_hash}\" }}"),
+        );
+
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert!(service.hash().unwrap().starts_with("sha256-v"));
+        assert!(service.tag_matches().unwrap());
+
+        workspace.write("packages/api/src/lib.rs", "pub fn api() {}");
+
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert!(!service.tag_matches().unwrap());
+    }
+
+    #[test]
+    fn test_check_dependencies_hash() {
+        let workspace = TestWorkspace::new("deps-hash");
+        workspace
+            .add_package("api", &[], "")
+            .add_package("service", &["api"], "");
+
+        let deps_hash = |workspace: &TestWorkspace| {
+            workspace
+                .context()
+                .resolve_package_by_name("service")
+                .unwrap()
+                .dependencies_hash()
+                .unwrap()
+        };
+        let check = |workspace: &TestWorkspace| {
+            workspace
+                .context()
+                .resolve_package_by_name("service")
+                .unwrap()
+                .check_dependencies_hash()
+                .map_err(|err| err.description().to_string())
+        };
+
+        // Without a recorded hash, there is nothing to check.
+        assert_eq!(check(&workspace), Ok(()));
+
+        let hash = deps_hash(&workspace);
+        workspace.add_package("service", &["api"], &format!("deps_hash = \"{hash}\""));
+        assert_eq!(check(&workspace), Ok(()));
+
+        // Changes to the package itself do not affect its dependencies hash.
+        workspace.write("packages/service/src/lib.rs", "pub fn service() {}");
+        assert_eq!(check(&workspace), Ok(()));
+
+        // Changes to its dependencies do.
+        workspace.write("packages/api/src/lib.rs", "pub fn api() {}");
+        assert_eq!(
+            check(&workspace),
+            Err("dependencies hash mismatch".to_string())
+        );
+
+        workspace.add_package(
+            "service",
+            &["api"],
+            &format!("deps_hash = \"sha256:{}\"", "0".repeat(64)),
+        );
+        assert_eq!(
+            check(&workspace),
+            Err("dependencies hash format mismatch".to_string())
+        );
     }
 }
@@ -0,0 +1,135 @@
+//! Machine-readable descriptions of packages.
+
+use std::path::PathBuf;
+
+use log::debug;
+use serde::Serialize;
+
+use crate::{Package, Result};
+
+/// A description of a package and of its distribution targets.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageRecord {
+    pub name: String,
+    pub version: String,
+    pub manifest_path: PathBuf,
+    pub root: PathBuf,
+    pub hash: String,
+    /// The hash recorded in the tag of the current version, if any.
+    pub tag: Option<String>,
+    pub tag_matches: bool,
+    pub dist_targets: Vec<DistTargetRecord>,
+}
+
+/// A description of a distribution target.
+#[derive(Debug, Clone, Serialize)]
+pub struct DistTargetRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// Where the distribution target is published: the name of a Docker image
+    /// or the URL of an S3 object.
+    ///
+    /// `None` if it depends on an environment variable that is not set.
+    pub destination: Option<String>,
+}
+
+impl PackageRecord {
+    pub(crate) fn new(package: &Package<'_>) -> Result<Self> {
+        let dist_targets = package
+            .monorepo_metadata()
+            .dist_targets(package)
+            .iter()
+            .map(|dist_target| DistTargetRecord {
+                name: dist_target.name().to_string(),
+                type_name: dist_target.type_name().to_string(),
+                destination: dist_target
+                    .destination()
+                    .map_err(|err| {
+                        debug!(
+                            "Could not resolve the destination of {}: {}",
+                            dist_target, err
+                        );
+                    })
+                    .ok(),
+            })
+            .collect();
+
+        Ok(Self {
+            name: package.name().to_string(),
+            version: package.version().to_string(),
+            manifest_path: package.manifest_path().to_path_buf(),
+            root: package.root().to_path_buf(),
+            hash: package.hash()?,
+            tag: package.get_tag(package.version()).cloned(),
+            tag_matches: package.tag_matches()?,
+            dist_targets,
+        })
+    }
+
+    /// Get the record as a line of tab-separated values.
+    ///
+    /// Distribution targets are listed in the last column, separated by
+    /// commas, as `<type>:<name>=<destination>`.
+    pub fn to_tsv(&self) -> String {
+        let dist_targets: Vec<_> = self
+            .dist_targets
+            .iter()
+            .map(|dist_target| {
+                format!(
+                    "{}:{}={}",
+                    dist_target.type_name,
+                    dist_target.name,
+                    dist_target.destination.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+
+        [
+            self.name.clone(),
+            self.version.clone(),
+            self.manifest_path.display().to_string(),
+            self.root.display().to_string(),
+            self.hash.clone(),
+            self.tag.clone().unwrap_or_default(),
+            self.tag_matches.to_string(),
+            dist_targets.join(","),
+        ]
+        .join("\t")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_record_to_tsv() {
+        let record = PackageRecord {
+            name: "service".to_string(),
+            version: "0.1.0".to_string(),
+            manifest_path: PathBuf::from("/repo/service/Cargo.toml"),
+            root: PathBuf::from("/repo/service"),
+            hash: "sha256-v7:abcd".to_string(),
+            tag: None,
+            tag_matches: false,
+            dist_targets: vec![
+                DistTargetRecord {
+                    name: "service-image".to_string(),
+                    type_name: "docker".to_string(),
+                    destination: Some("registry/service:0.1.0".to_string()),
+                },
+                DistTargetRecord {
+                    name: "service-lambda".to_string(),
+                    type_name: "aws-lambda".to_string(),
+                    destination: None,
+                },
+            ],
+        };
+
+        assert_eq!(
+            record.to_tsv(),
+            "service\t0.1.0\t/repo/service/Cargo.toml\t/repo/service\tsha256-v7:abcd\t\tfalse\tdocker:service-image=registry/service:0.1.0,aws-lambda:service-lambda="
+        );
+    }
+}
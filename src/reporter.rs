@@ -0,0 +1,487 @@
+//! Reporting of the progress of a run.
+//!
+//! All progress goes through a global [`Reporter`], set once by the binary:
+//! the steps printed with [`action_step!`](crate::action_step) and
+//! [`ignore_step!`](crate::ignore_step) as well as structured events about the
+//! operations on packages and distribution targets, the commands run and the
+//! artifacts produced.
+//!
+//! An ignore step reported while an operation is in progress marks that
+//! operation as skipped, with the step as its reason.
+
+use std::{fmt::Display, io::Write, sync::Mutex, time::Instant};
+
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+
+use crate::{
+    run_log,
+    term::{print_step, ACTION_STEP_COLOR, IGNORE_STEP_COLOR},
+    Error, Package, Result,
+};
+
+static REPORTER: OnceCell<Box<dyn Reporter>> = OnceCell::new();
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State::new()));
+
+/// A sink for the events of a run.
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+/// Set the reporter of the run.
+///
+/// Until it is called, events are reported as human-readable text.
+pub fn set_reporter(reporter: Box<dyn Reporter>) {
+    // The duration of the run is measured from here.
+    Lazy::force(&STATE);
+
+    assert!(
+        REPORTER.set(reporter).is_ok(),
+        "the reporter can only be set once"
+    );
+}
+
+/// How much of the progress is printed as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Nothing is printed: errors are reported by the binary.
+    Silent,
+    /// Only the ignore steps are printed.
+    Quiet,
+    #[default]
+    Normal,
+}
+
+/// The kind of a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    Action,
+    Ignore,
+}
+
+/// An operation on a package or on one of its distribution targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    Build,
+    Publish,
+    Execute,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Build => write!(f, "build"),
+            Self::Publish => write!(f, "publish"),
+            Self::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// The status an operation ended with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Finished,
+    Skipped,
+    Failed,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Finished => write!(f, "finished"),
+            Self::Skipped => write!(f, "skipped"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// What an operation is performed on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Subject {
+    pub package: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist_target: Option<DistTargetSubject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DistTargetSubject {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+impl Subject {
+    pub(crate) fn package(package: &Package<'_>) -> Self {
+        Self {
+            package: package.name().to_string(),
+            version: package.version().to_string(),
+            dist_target: None,
+        }
+    }
+
+    pub(crate) fn dist_target(package: &Package<'_>, name: &str, type_name: &str) -> Self {
+        Self {
+            dist_target: Some(DistTargetSubject {
+                name: name.to_string(),
+                type_name: type_name.to_string(),
+            }),
+            ..Self::package(package)
+        }
+    }
+}
+
+/// The outcome of an operation, as listed in the summary.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OperationOutcome {
+    #[serde(flatten)]
+    pub subject: Subject,
+    pub operation: Operation,
+    pub status: Status,
+    /// Why the operation was skipped or failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub duration_secs: f64,
+}
+
+/// An event of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A free-form progress step.
+    Step {
+        kind: StepKind,
+        action: String,
+        description: String,
+    },
+    Started {
+        #[serde(flatten)]
+        subject: Subject,
+        operation: Operation,
+    },
+    Ended(OperationOutcome),
+    /// A command was run.
+    Command {
+        command: String,
+    },
+    /// An artifact was produced: a file, a Docker image or an S3 object.
+    Artifact {
+        #[serde(flatten)]
+        subject: Subject,
+        location: String,
+    },
+    /// The run failed.
+    Error {
+        description: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        explanation: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output: Option<String>,
+    },
+    /// The final summary of the run.
+    Summary {
+        success: bool,
+        duration_secs: f64,
+        operations: Vec<OperationOutcome>,
+    },
+}
+
+struct State {
+    start: Instant,
+    /// The operations in progress, innermost last, with the reason why they
+    /// were skipped, if any.
+    operations: Vec<(Subject, Operation, Instant, Option<String>)>,
+    outcomes: Vec<OperationOutcome>,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            operations: Vec::new(),
+            outcomes: Vec::new(),
+        }
+    }
+}
+
+/// Report an event.
+///
+/// Events are also written to the log file of the run, if any.
+pub fn report(event: &Event) {
+    if run_log::is_enabled() {
+        run_log::write_line(&serde_json::to_string(event).unwrap());
+    }
+
+    match REPORTER.get() {
+        Some(reporter) => reporter.report(event),
+        None => HumanReporter::default().report(event),
+    }
+}
+
+/// Report a step.
+pub fn step(kind: StepKind, action: &str, description: impl Display) {
+    let description = description.to_string();
+
+    if kind == StepKind::Ignore {
+        if let Some((_, _, _, reason)) = STATE.lock().unwrap().operations.last_mut() {
+            reason.get_or_insert_with(|| format!("{action} {description}"));
+        }
+    }
+
+    report(&Event::Step {
+        kind,
+        action: action.to_string(),
+        description,
+    });
+}
+
+/// Report a command that is about to be run.
+pub(crate) fn command(command: String) {
+    report(&Event::Command { command });
+}
+
+/// Report an artifact that was produced.
+pub(crate) fn artifact(subject: Subject, location: String) {
+    report(&Event::Artifact { subject, location });
+}
+
+/// Perform an operation, reporting when it starts and ends.
+pub(crate) fn operation<T>(
+    subject: Subject,
+    operation: Operation,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    report(&Event::Started {
+        subject: subject.clone(),
+        operation,
+    });
+
+    STATE
+        .lock()
+        .unwrap()
+        .operations
+        .push((subject, operation, Instant::now(), None));
+
+    let result = f();
+
+    let outcome = {
+        let (subject, operation, start, skip_reason) =
+            STATE.lock().unwrap().operations.pop().unwrap();
+
+        let (status, reason) = match (&result, skip_reason) {
+            (Err(err), _) => (Status::Failed, Some(err.description().to_string())),
+            (Ok(_), Some(reason)) => (Status::Skipped, Some(reason)),
+            (Ok(_), None) => (Status::Finished, None),
+        };
+
+        OperationOutcome {
+            subject,
+            operation,
+            status,
+            reason,
+            duration_secs: start.elapsed().as_secs_f64(),
+        }
+    };
+
+    STATE.lock().unwrap().outcomes.push(outcome.clone());
+    report(&Event::Ended(outcome));
+
+    result
+}
+
+/// Report the error a run failed with.
+pub fn error(err: &Error) {
+    report(&Event::Error {
+        description: err.description().to_string(),
+        source: err.source().map(ToString::to_string),
+        explanation: err.explanation().map(ToString::to_string),
+        output: err.output().map(ToString::to_string),
+    });
+}
+
+/// Report the summary of the run.
+pub fn summary(success: bool) {
+    let (duration, operations) = {
+        let state = STATE.lock().unwrap();
+
+        (state.start.elapsed(), state.outcomes.clone())
+    };
+
+    report(&Event::Summary {
+        success,
+        duration_secs: duration.as_secs_f64(),
+        operations,
+    });
+}
+
+/// Reports events as padded, colored text.
+///
+/// Operations on packages and the summary are not printed, as they are
+/// already described by the steps and the error, if any.
+#[derive(Debug, Default)]
+pub struct HumanReporter {
+    verbosity: Verbosity,
+}
+
+impl HumanReporter {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Self { verbosity }
+    }
+}
+
+impl Reporter for HumanReporter {
+    fn report(&self, event: &Event) {
+        match self.verbosity {
+            Verbosity::Silent => return,
+            Verbosity::Quiet => {
+                if !matches!(
+                    event,
+                    Event::Step {
+                        kind: StepKind::Ignore,
+                        ..
+                    }
+                ) {
+                    return;
+                }
+            }
+            Verbosity::Normal => {}
+        }
+
+        match event {
+            Event::Step {
+                kind: StepKind::Action,
+                action,
+                description,
+            } => print_step(ACTION_STEP_COLOR, action, description),
+            Event::Step {
+                kind: StepKind::Ignore,
+                action,
+                description,
+            } => print_step(IGNORE_STEP_COLOR, action, description),
+            Event::Started {
+                subject:
+                    Subject {
+                        package,
+                        dist_target: Some(dist_target),
+                        ..
+                    },
+                operation,
+            } => print_step(
+                ACTION_STEP_COLOR,
+                match operation {
+                    Operation::Publish => "Publishing",
+                    Operation::Build | Operation::Execute => "Building",
+                },
+                format!("distribution {}[{}]", dist_target.type_name, package),
+            ),
+            Event::Ended(OperationOutcome {
+                subject:
+                    Subject {
+                        dist_target: Some(_),
+                        ..
+                    },
+                operation,
+                status: Status::Finished,
+                duration_secs,
+                ..
+            }) => print_step(
+                ACTION_STEP_COLOR,
+                "Finished",
+                format!(
+                    "{} in {:.2}s",
+                    match operation {
+                        Operation::Publish => "publication",
+                        Operation::Build | Operation::Execute => "distribution",
+                    },
+                    duration_secs
+                ),
+            ),
+            Event::Command { command } => {
+                print_step(ACTION_STEP_COLOR, "Running", format!("`{command}`"));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reports events as JSON Lines, in the spirit of
+/// `cargo --message-format json`.
+pub struct JsonReporter {
+    to_stderr: bool,
+}
+
+impl JsonReporter {
+    /// Report events on the standard output.
+    pub fn stdout() -> Self {
+        Self { to_stderr: false }
+    }
+
+    /// Report events on the standard error, for commands that print their
+    /// results on the standard output.
+    pub fn stderr() -> Self {
+        Self { to_stderr: true }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &Event) {
+        let line = serde_json::to_string(event).unwrap();
+
+        if self.to_stderr {
+            let stderr = std::io::stderr();
+            let mut stderr = stderr.lock();
+
+            writeln!(&mut stderr, "{line}").unwrap();
+            stderr.flush().unwrap();
+        } else {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+
+            writeln!(&mut stdout, "{line}").unwrap();
+            stdout.flush().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        let subject = Subject {
+            package: "service".to_string(),
+            version: "0.1.0".to_string(),
+            dist_target: Some(DistTargetSubject {
+                name: "service-image".to_string(),
+                type_name: "docker".to_string(),
+            }),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&Event::Started {
+                subject: subject.clone(),
+                operation: Operation::Build,
+            })
+            .unwrap(),
+            r#"{"event":"started","package":"service","version":"0.1.0","dist_target":{"name":"service-image","type":"docker"},"operation":"build"}"#
+        );
+
+        assert_eq!(
+            serde_json::to_string(&Event::Ended(OperationOutcome {
+                subject,
+                operation: Operation::Publish,
+                status: Status::Skipped,
+                reason: Some("Up-to-date image exists".to_string()),
+                duration_secs: 0.5,
+            }))
+            .unwrap(),
+            r#"{"event":"ended","package":"service","version":"0.1.0","dist_target":{"name":"service-image","type":"docker"},"operation":"publish","status":"skipped","reason":"Up-to-date image exists","duration_secs":0.5}"#
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Persistent logs of a run.
+//!
+//! When a log file is opened, it captures the debug logs, the reported events
+//! and the output of the commands that are run, whatever is shown on the
+//! terminal, so that a failed run can be diagnosed after the fact.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::{Error, Result};
+
+static LOG_FILE: OnceCell<Mutex<File>> = OnceCell::new();
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Open the log file of the run, truncating it if it exists.
+pub fn open(path: &Path) -> Result<()> {
+    let file = File::create(path).map_err(|err| {
+        Error::new("failed to create log file")
+            .with_source(err)
+            .with_output(format!("Path: {}", path.display()))
+    })?;
+
+    Lazy::force(&START);
+
+    if LOG_FILE.set(Mutex::new(file)).is_err() {
+        return Err(Error::new("log file is already open"));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_enabled() -> bool {
+    LOG_FILE.get().is_some()
+}
+
+/// Write a line to the log file, if any, prefixed by the time elapsed since
+/// the start of the run.
+pub(crate) fn write_line(line: &str) {
+    if let Some(file) = LOG_FILE.get() {
+        // A log file that cannot be written to must not fail the run.
+        writeln!(
+            file.lock().unwrap(),
+            "{}",
+            format_line(START.elapsed(), line)
+        )
+        .ok();
+    }
+}
+
+fn format_line(elapsed: Duration, line: &str) -> String {
+    format!("[{:>9.3}s] {}", elapsed.as_secs_f64(), line)
+}
+
+/// Install a logger that logs to the terminal as the specified logger does,
+/// and all the debug logs to the log file, if any.
+pub fn init_logger(terminal: env_logger::Logger) {
+    let max_level = if is_enabled() {
+        terminal.filter().max(log::LevelFilter::Debug)
+    } else {
+        terminal.filter()
+    };
+
+    log::set_boxed_logger(Box::new(RunLogger { terminal })).unwrap();
+    log::set_max_level(max_level);
+}
+
+struct RunLogger {
+    terminal: env_logger::Logger,
+}
+
+impl log::Log for RunLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.terminal.enabled(metadata) || (is_enabled() && metadata.level() <= log::Level::Debug)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.terminal.matches(record) {
+            self.terminal.log(record);
+        }
+
+        if record.level() <= log::Level::Debug {
+            write_line(&format!(
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+
+        if let Some(file) = LOG_FILE.get() {
+            file.lock().unwrap().flush().ok();
+        }
+    }
+}
+
+/// Run a command to completion, copying its output to the log file, if any.
+///
+/// If `show_output` is set, the output of the command goes to the terminal and
+/// is not part of the returned output. Otherwise, it is captured and returned.
+///
+/// When a log file is open, the output of the command is always piped, so that
+/// it can be copied to the log file: commands that only use colors or progress
+/// bars on an interactive terminal do not use them then.
+pub(crate) fn run_command(cmd: &mut Command, show_output: bool) -> std::io::Result<Output> {
+    if !is_enabled() {
+        return if show_output {
+            cmd.status().map(|status| Output {
+                status,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            })
+        } else {
+            cmd.output()
+        };
+    }
+
+    write_line(&format!("Running {cmd:?}"));
+
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let stdout = std::thread::spawn(move || {
+        let mut terminal = std::io::stdout();
+
+        tee(
+            stdout,
+            show_output.then_some(&mut terminal as &mut dyn Write),
+            |line| write_line(&format!("stdout | {line}")),
+        )
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut terminal = std::io::stderr();
+
+        tee(
+            stderr,
+            show_output.then_some(&mut terminal as &mut dyn Write),
+            |line| write_line(&format!("stderr | {line}")),
+        )
+    });
+
+    let status = child.wait()?;
+    let stdout = stdout.join().unwrap()?;
+    let stderr = stderr.join().unwrap()?;
+
+    write_line(&format!("Command exited with {status}"));
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Copy the specified stream, line by line, to the log and, if any, to the
+/// terminal.
+///
+/// Whole lines are logged, so that the lines of the different streams of a
+/// command do not interleave in the log file. The data is returned unless it
+/// went to the terminal.
+fn tee(
+    reader: impl Read,
+    mut terminal: Option<&mut dyn Write>,
+    mut log: impl FnMut(&str),
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(reader);
+    let mut data = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        log(String::from_utf8_lossy(&line).trim_end_matches(&['\r', '\n'][..]));
+
+        match &mut terminal {
+            Some(terminal) => {
+                terminal.write_all(&line)?;
+                terminal.flush()?;
+            }
+            None => data.extend_from_slice(&line),
+        }
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line(Duration::from_millis(1500), "Running \"cargo\""),
+            "[    1.500s] Running \"cargo\""
+        );
+        assert_eq!(
+            format_line(Duration::from_secs(12345), "done"),
+            "[12345.000s] done"
+        );
+    }
+
+    #[test]
+    fn test_tee() {
+        let input: &[u8] = b"first\r\nsecond\n\nlast";
+
+        let mut lines = Vec::new();
+        let data = tee(input, None, |line| lines.push(line.to_string())).unwrap();
+
+        assert_eq!(data, input);
+        assert_eq!(lines, ["first", "second", "", "last"]);
+
+        let mut terminal = Vec::new();
+        let mut lines = Vec::new();
+        let data = tee(input, Some(&mut terminal), |line| {
+            lines.push(line.to_string());
+        })
+        .unwrap();
+
+        assert!(data.is_empty());
+        assert_eq!(terminal, input);
+        assert_eq!(lines, ["first", "second", "", "last"]);
+
+        // Invalid UTF-8 is logged lossily, but passed through as-is.
+        let input: &[u8] = b"caf\xe9\n";
+        let mut lines = Vec::new();
+        assert_eq!(
+            tee(input, None, |line| lines.push(line.to_string())).unwrap(),
+            input
+        );
+        assert_eq!(lines, ["caf\u{fffd}"]);
+    }
+}
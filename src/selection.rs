@@ -0,0 +1,482 @@
+//! Selection of the packages a command operates on.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+use log::debug;
+
+use crate::{
+    git::GitChanges, metadata::Metadata, ChangeExplanation, Context, Error, Package, Result,
+};
+
+/// The packages a command operates on.
+///
+/// The packages are first selected by their changes - if any change criteria
+/// is specified - then filtered by the specified package specs and groups,
+/// expanded with their dependencies or dependants and finally filtered by the
+/// exclusions and distribution target types.
+#[derive(Debug, Clone, Default)]
+pub struct PackageSelection {
+    /// The package specs: names, glob patterns or `name@version`.
+    ///
+    /// If empty, all the packages are selected.
+    pub specs: Vec<PackageSpec>,
+    /// Only select the packages that belong to one of these groups.
+    pub groups: Vec<String>,
+    /// The specs of the packages to exclude from the selection.
+    pub exclude: Vec<PackageSpec>,
+    /// Only select the packages affected by these Git changes.
+    pub changes: Option<GitChanges>,
+    /// Only select the packages whose hash does not match their tag.
+    pub untagged: bool,
+    /// Add the workspace packages the selected packages depend on.
+    pub with_dependencies: bool,
+    /// Add the workspace packages that depend on the selected packages.
+    pub with_dependants: bool,
+    /// Only select the packages that have a distribution target of one of
+    /// these types.
+    pub dist_target_types: Vec<String>,
+}
+
+impl PackageSelection {
+    /// Resolve the selected packages.
+    ///
+    /// Packages selected by their changes, or expanded with their dependencies
+    /// or dependants, are returned in dependency order.
+    pub fn resolve<'g>(&self, context: &'g Context) -> Result<Vec<Package<'g>>> {
+        Ok(self
+            .resolve_explained(context)?
+            .into_iter()
+            .map(|(package, _)| package)
+            .collect())
+    }
+
+    /// Resolve the selected packages like `resolve`, along with the reason why
+    /// each package is considered changed, for the packages selected by their
+    /// Git changes.
+    pub fn resolve_explained<'g>(
+        &self,
+        context: &'g Context,
+    ) -> Result<Vec<(Package<'g>, Option<ChangeExplanation>)>> {
+        if self.changes.is_some() && self.untagged {
+            return Err(
+                Error::new("conflicting package selection").with_explanation(
+                    "Packages can be selected by their Git changes or by their tags, but not both.",
+                ),
+            );
+        }
+
+        for spec in &self.specs {
+            if !context
+                .workspace_packages_metadata()
+                .any(|package_metadata| {
+                    spec.matches(package_metadata.name(), package_metadata.version())
+                })
+            {
+                return Err(Error::new("package not found")
+                    .with_explanation(format!("No package of the workspace matches `{spec}`.")));
+            }
+        }
+
+        let workspace_packages = context
+            .workspace_packages_metadata()
+            .map(|package_metadata| {
+                Metadata::new(&package_metadata).map(|metadata| (package_metadata, metadata))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for group in &self.groups {
+            if !workspace_packages
+                .iter()
+                .any(|(_, metadata)| metadata.groups.contains(group))
+            {
+                return Err(Error::new("group not found").with_explanation(format!(
+                    "No package of the workspace belongs to the group `{group}`."
+                )));
+            }
+        }
+
+        let mut explanations = HashMap::new();
+
+        let mut packages = if let Some(changes) = &self.changes {
+            context
+                .explain_changed_packages(changes)?
+                .into_iter()
+                .map(|(package, explanation)| {
+                    explanations.insert(package.id().clone(), explanation);
+
+                    package
+                })
+                .collect()
+        } else if self.untagged {
+            context.resolve_untagged_packages()?
+        } else {
+            // Loading a package digests its sources: only load the packages
+            // whose metadata matches the selection.
+            let expanding = self.with_dependencies || self.with_dependants;
+
+            workspace_packages
+                .into_iter()
+                .filter(|(package_metadata, metadata)| {
+                    self.matches_specs(package_metadata.name(), package_metadata.version())
+                        && self.matches_groups(&metadata.groups)
+                        // Excluded packages can still bring their dependencies
+                        // or dependants in.
+                        && (expanding
+                            || !self.is_excluded(package_metadata.name(), package_metadata.version()))
+                })
+                .sorted_by(|(a, _), (b, _)| a.name().cmp(b.name()))
+                .map(|(package_metadata, _)| Package::new(context, package_metadata))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        packages.retain(|package| {
+            self.matches_specs(package.name(), package.version())
+                && self.matches_groups(package.groups())
+        });
+
+        if self.with_dependencies || self.with_dependants {
+            packages = self.expand(context, packages)?;
+        }
+
+        packages.retain(|package| !self.is_excluded(package.name(), package.version()));
+
+        if !self.dist_target_types.is_empty() {
+            packages.retain(|package| {
+                self.dist_target_types
+                    .iter()
+                    .any(|type_name| package.has_dist_target_type(type_name))
+            });
+        }
+
+        Ok(packages
+            .into_iter()
+            .map(|package| {
+                let explanation = explanations.remove(package.id());
+
+                (package, explanation)
+            })
+            .collect())
+    }
+
+    fn matches_specs(&self, name: &str, version: &semver::Version) -> bool {
+        self.specs.is_empty() || self.specs.iter().any(|spec| spec.matches(name, version))
+    }
+
+    fn matches_groups(&self, groups: &BTreeSet<String>) -> bool {
+        self.groups.is_empty() || self.groups.iter().any(|group| groups.contains(group))
+    }
+
+    fn is_excluded(&self, name: &str, version: &semver::Version) -> bool {
+        self.exclude.iter().any(|spec| spec.matches(name, version))
+    }
+
+    fn expand<'g>(
+        &self,
+        context: &'g Context,
+        packages: Vec<Package<'g>>,
+    ) -> Result<Vec<Package<'g>>> {
+        let ids: Vec<_> = packages
+            .iter()
+            .map(|package| package.id().clone())
+            .collect();
+        let mut expanded_ids = HashSet::new();
+
+        if self.with_dependencies {
+            expanded_ids.extend(context.dependency_package_ids(&ids)?);
+        }
+
+        if self.with_dependants {
+            expanded_ids.extend(context.dependant_package_ids(&ids)?);
+        }
+
+        debug!(
+            "Expanded the selection from {} to {} packages",
+            ids.len(),
+            expanded_ids.len()
+        );
+
+        let mut packages: Vec<_> = packages.into_iter().map(Some).collect();
+
+        // The dependants of the expanded packages are walked dependencies
+        // first: keeping only the expanded packages gives them in dependency
+        // order.
+        context
+            .dependant_package_ids(expanded_ids.iter().copied())?
+            .into_iter()
+            .filter(|id| expanded_ids.contains(id))
+            .map(|id| {
+                match packages
+                    .iter_mut()
+                    .find(|package| package.as_ref().is_some_and(|p| p.id() == id))
+                {
+                    Some(package) => Ok(package.take().unwrap()),
+                    None => Package::new(context, context.package_metadata(id)?),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A package spec: a name - possibly a glob pattern - and an optional version,
+/// written `name@version`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageSpec {
+    name: glob::Pattern,
+    version: Option<semver::Version>,
+}
+
+impl PackageSpec {
+    pub fn matches(&self, name: &str, version: &semver::Version) -> bool {
+        self.name.matches(name) && self.version.as_ref().is_none_or(|v| v == version)
+    }
+}
+
+impl FromStr for PackageSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, version) = match s.split_once('@') {
+            Some((name, version)) => (
+                name,
+                Some(version.parse().map_err(|err| {
+                    Error::new("invalid package version")
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "The version in the package spec `{s}` is not a valid semantic version."
+                        ))
+                })?),
+            ),
+            None => (s, None),
+        };
+
+        let name = glob::Pattern::new(name).map_err(|err| {
+            Error::new("failed to read glob pattern")
+                .with_source(err)
+                .with_explanation("The specified package pattern could not be parsed. You may want to double-check for syntax errors.")
+                .with_output(format!("Pattern: {name}"))
+        })?;
+
+        Ok(Self { name, version })
+    }
+}
+
+impl std::fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_workspace::TestWorkspace;
+
+    fn names(packages: &[Package<'_>]) -> Vec<String> {
+        packages
+            .iter()
+            .map(|package| package.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_only_loads_selected_packages() {
+        let workspace = TestWorkspace::new("selection");
+        workspace
+            .add_package("api", &[], "groups = [\"backend\"]")
+            .add_package("api-client", &["api"], "")
+            .add_package("web", &[], "groups = [\"frontend\"]")
+            // Loading this package fails, as its `hash_include` pattern is
+            // invalid.
+            .add_package("broken", &[], "hash_include = [\"[\"]");
+        let context = workspace.context();
+
+        let select = |selection: PackageSelection| {
+            selection.resolve(&context).map(|packages| names(&packages))
+        };
+        let specs = |specs: &[&str]| specs.iter().map(|spec| spec.parse().unwrap()).collect();
+
+        assert_eq!(
+            select(PackageSelection {
+                specs: specs(&["api*"]),
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["api", "api-client"]
+        );
+        assert_eq!(
+            select(PackageSelection {
+                groups: vec!["frontend".to_string()],
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["web"]
+        );
+        assert_eq!(
+            select(PackageSelection {
+                specs: specs(&["api-client"]),
+                with_dependencies: true,
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["api", "api-client"]
+        );
+        assert_eq!(
+            select(PackageSelection {
+                exclude: specs(&["broken"]),
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["api", "api-client", "web"]
+        );
+
+        assert_eq!(
+            select(PackageSelection {
+                specs: specs(&["api*"]),
+                groups: vec!["backend".to_string()],
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["api"]
+        );
+        // Packages brought in as dependants do not have to be part of the
+        // selected groups.
+        assert_eq!(
+            select(PackageSelection {
+                groups: vec!["backend".to_string()],
+                with_dependants: true,
+                ..PackageSelection::default()
+            })
+            .unwrap(),
+            ["api", "api-client"]
+        );
+
+        assert!(select(PackageSelection::default()).is_err());
+        assert!(select(PackageSelection {
+            groups: vec!["unknown".to_string()],
+            ..PackageSelection::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve_untagged_packages() {
+        let workspace = TestWorkspace::new("untagged");
+        workspace
+            .add_package("api", &[], "")
+            .add_package("service", &["api"], "")
+            .add_package("web", &[], "");
+
+        let untagged = |workspace: &TestWorkspace, specs: &[&str]| {
+            PackageSelection {
+                specs: specs.iter().map(|spec| spec.parse().unwrap()).collect(),
+                untagged: true,
+                ..PackageSelection::default()
+            }
+            .resolve(&workspace.context())
+            .map(|packages| names(&packages))
+        };
+
+        // Packages come in dependency order.
+        let packages = untagged(&workspace, &[]).unwrap();
+        let position = |name: &str| packages.iter().position(|package| package == name);
+        assert_eq!(packages.len(), 3);
+        assert!(position("api") < position("service"));
+        assert!(position("web").is_some());
+
+        for package in workspace.context().packages().unwrap() {
+            package.tag().unwrap();
+        }
+
+        assert_eq!(untagged(&workspace, &[]).unwrap(), Vec::<String>::new());
+
+        // The packages that depend on an untagged package are untagged too.
+        workspace.write("packages/api/src/lib.rs", "pub fn api() {}");
+        assert_eq!(untagged(&workspace, &[]).unwrap(), ["api", "service"]);
+        assert_eq!(untagged(&workspace, &["service"]).unwrap(), ["service"]);
+
+        // Tags and Git changes cannot be combined.
+        assert!(PackageSelection {
+            untagged: true,
+            changes: Some(GitChanges::since_merge_base("main")),
+            ..PackageSelection::default()
+        }
+        .resolve(&workspace.context())
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve_explained() {
+        let workspace = TestWorkspace::new("selection-explained");
+        workspace
+            .add_package("api", &[], "")
+            .add_package("service", &["api"], "")
+            .add_package("web", &[], "");
+        workspace.context();
+        workspace.commit("Initial commit");
+        workspace.write("packages/service/src/lib.rs", "pub fn service() {}");
+
+        let context = workspace.context();
+        let explained = |selection: PackageSelection| {
+            selection
+                .resolve_explained(&context)
+                .unwrap()
+                .into_iter()
+                .map(|(package, explanation)| {
+                    (
+                        package.name().to_string(),
+                        explanation.map(|explanation| explanation.package),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let changes = Some(GitChanges::new(crate::GitRange::Since("HEAD".to_string())));
+
+        assert_eq!(
+            explained(PackageSelection {
+                changes: changes.clone(),
+                ..PackageSelection::default()
+            }),
+            [("service".to_string(), Some("service".to_string()))]
+        );
+        // Packages that are only selected as dependencies are not explained.
+        assert_eq!(
+            explained(PackageSelection {
+                changes,
+                with_dependencies: true,
+                ..PackageSelection::default()
+            }),
+            [
+                ("api".to_string(), None),
+                ("service".to_string(), Some("service".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_package_spec() {
+        let version = |v: &str| semver::Version::parse(v).unwrap();
+        let spec = |s: &str| s.parse::<PackageSpec>().unwrap();
+
+        assert!(spec("api").matches("api", &version("0.1.0")));
+        assert!(!spec("api").matches("api-client", &version("0.1.0")));
+
+        assert!(spec("svc-*").matches("svc-payments", &version("1.0.0")));
+        assert!(!spec("svc-*").matches("api", &version("1.0.0")));
+
+        assert!(spec("api@0.2.0").matches("api", &version("0.2.0")));
+        assert!(!spec("api@0.2.0").matches("api", &version("0.1.0")));
+        assert!(spec("svc-*@1.0.0").matches("svc-payments", &version("1.0.0")));
+
+        assert!("api@latest".parse::<PackageSpec>().is_err());
+        assert!("svc-[".parse::<PackageSpec>().is_err());
+
+        assert_eq!(spec("svc-*@1.0.0").to_string(), "svc-*@1.0.0");
+    }
+}
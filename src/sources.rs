@@ -1,29 +1,107 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    io::{BufRead, Write},
     iter::once,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use cargo::core::Source;
-use serde::Serialize;
+use log::debug;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{context::Context, Error, Result};
+use crate::{
+    context::{Context, Mode},
+    dep_info,
+    hash::HashAlgorithm,
+    hash_cache::HashCache,
+    hash_manifest::HASH_MANIFESTS_FILE_NAME,
+    metadata::Metadata,
+    Error, ErrorContext, Result,
+};
 
 /// Represent the sources files for a package.
 ///
 /// This structure does not only contain the rust source files but any file that
 /// belong to - and that can possibly be used by - the package.
 ///
-/// As an exception, the manifest file is never included in this structure.
+/// Only the digest of each file is kept, so that memory usage scales with the
+/// number of files rather than with their size. Files are digested in parallel
+/// and their digests are cached on disk. Along with its digest, the executable
+/// bit of each file is recorded, and symbolic links are recorded by their
+/// target rather than by the content they point to.
+///
+/// The files of the path dependencies that are not part of the workspace are
+/// included as well and, if enabled in the workspace metadata, the files that
+/// the dep-info files of the last build reference.
+///
+/// Files are keyed by their path relative to the package root, using `/` as a
+/// separator regardless of the platform, so that the structure - and any hash
+/// derived from it - does not depend on where the repository is checked out.
+/// The files of path dependencies, which can live outside of the repository,
+/// are keyed by their path relative to the root of their dependency instead,
+/// prefixed with `path-dependency:<name>/`.
+///
+/// As an exception, the manifest file and the hash manifests file are never
+/// included in this structure.
 #[derive(Debug, Clone, Serialize)]
-pub struct Sources(BTreeMap<PathBuf, Vec<u8>>);
+pub struct Sources {
+    #[serde(skip)]
+    root: PathBuf,
+    /// The roots of the path dependencies, by the prefix of their keys.
+    #[serde(skip)]
+    path_dependencies: Vec<(String, PathBuf)>,
+    files: BTreeMap<String, SourceFile>,
+}
+
+/// A file of the sources, as it takes part in the hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFile {
+    /// A regular file.
+    ///
+    /// Only the executable bit of the mode is recorded, like Git does: the
+    /// other permission bits depend on the umask of whoever checked out the
+    /// repository.
+    File { digest: String, executable: bool },
+    /// A symbolic link, which is never followed.
+    Symlink { target: String },
+}
+
+impl Display for SourceFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File {
+                digest,
+                executable: false,
+            } => write!(f, "{digest}"),
+            Self::File {
+                digest,
+                executable: true,
+            } => write!(f, "{digest} (executable)"),
+            Self::Symlink { target } => write!(f, "symlink to {target}"),
+        }
+    }
+}
+
+/// The options used to digest files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct DigestOptions {
+    pub algorithm: HashAlgorithm,
+    /// Whether CRLF line endings are normalized to LF in text files.
+    #[serde(default)]
+    pub normalize_line_endings: bool,
+}
 
 impl Sources {
-    pub fn from_package(
+    pub(crate) fn from_package(
         context: &Context,
         package: &guppy::graph::PackageMetadata<'_>,
+        metadata: &Metadata,
+        options: DigestOptions,
     ) -> Result<Self> {
-        let workspace = &context.workspace()?;
+        let workspace = context.workspace();
         let core_package = workspace
             .members()
             .find(|pkg| pkg.name().as_str() == package.name())
@@ -34,42 +112,928 @@ impl Sources {
                 ))
             })?;
 
-        Self::new(workspace, core_package)
+        let path_dependencies: Vec<_> = context
+            .non_workspace_path_dependencies(package.id())?
+            .into_iter()
+            .map(|(name, root)| (format!("path-dependency:{name}"), normalize_path(root)))
+            .collect();
+
+        let dep_info_files = if context.workspace_metadata().dep_info_sources {
+            Self::dep_info_files(context, package, &path_dependencies)?
+        } else {
+            vec![]
+        };
+
+        let mut sources = Self::new(
+            context,
+            core_package,
+            metadata,
+            dep_info_files,
+            Self::path_dependency_files(workspace, &path_dependencies)?,
+            options,
+        )?;
+
+        sources.path_dependencies = path_dependencies;
+
+        Ok(sources)
     }
 
-    fn new(workspace: &cargo::core::Workspace<'_>, pkg: &cargo::core::Package) -> Result<Self> {
+    fn new(
+        context: &Context,
+        pkg: &cargo::core::Package,
+        metadata: &Metadata,
+        external_files: Vec<PathBuf>,
+        keyed_files: Vec<(String, PathBuf)>,
+        options: DigestOptions,
+    ) -> Result<Self> {
         let mut path_source = cargo::sources::PathSource::new(
             pkg.root(),
             pkg.package_id().source_id(),
-            workspace.config(),
+            context.workspace().config(),
         );
 
         path_source
             .update()
             .map_err(|err| Error::new("failed to update path source").with_source(err))?;
 
-        Ok(Self(
+        let root = pkg.root().to_path_buf();
+        let hash_manifests_path = root.join(HASH_MANIFESTS_FILE_NAME);
+        let included_files = Self::included_files(&root, &metadata.hash_include)?;
+        let exclude_patterns = Self::exclude_patterns(&metadata.hash_exclude)?;
+
+        let paths = path_source
+            .list_files(pkg)
+            .map_err(|err| Error::new("failed to list files").with_source(err))?
+            .into_iter()
+            .chain(once(pkg.manifest_path().to_path_buf()))
+            .chain(included_files)
+            .chain(external_files)
+            .filter(|path| path != pkg.manifest_path() && path != &hash_manifests_path)
+            .map(|path| Ok((relative_path(&root, &path)?, path)))
+            .chain(keyed_files.into_iter().map(Ok))
+            .filter(|entry| {
+                entry.as_ref().map_or(true, |(relative_path, _)| {
+                    !exclude_patterns
+                        .iter()
+                        .any(|pattern| pattern.matches(relative_path))
+                })
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let files = Self::source_files(
+            paths,
+            options,
+            context.hash_cache(),
+            context.git_file_modes(),
+        )?;
+
+        Ok(Self {
+            root,
+            path_dependencies: vec![],
+            files,
+        })
+    }
+
+    /// Digest the specified files in parallel, keeping their keys.
+    fn source_files(
+        paths: BTreeMap<String, PathBuf>,
+        options: DigestOptions,
+        hash_cache: &HashCache,
+        git_file_modes: &GitFileModes,
+    ) -> Result<BTreeMap<String, SourceFile>> {
+        paths
+            .into_par_iter()
+            .map(|(relative_path, path)| {
+                Ok((
+                    relative_path,
+                    Self::source_file(&path, options, hash_cache, git_file_modes)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// List the files of the specified path dependencies that are not part of
+    /// the workspace, as their changes would otherwise go unnoticed, keyed by
+    /// their path relative to the root of their dependency.
+    fn path_dependency_files(
+        workspace: &cargo::core::Workspace<'_>,
+        path_dependencies: &[(String, PathBuf)],
+    ) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+
+        for (prefix, root) in path_dependencies {
+            debug!(
+                "Including the files of path dependency `{}`",
+                root.display()
+            );
+
+            let source_id = cargo::core::SourceId::for_path(root)
+                .map_err(|err| Error::new("failed to resolve path dependency").with_source(err))?;
+            let mut path_source =
+                cargo::sources::PathSource::new(root, source_id, workspace.config());
+
             path_source
-                .list_files(pkg)
+                .update()
+                .map_err(|err| Error::new("failed to update path source").with_source(err))?;
+
+            let pkg = path_source
+                .root_package()
+                .map_err(|err| Error::new("failed to read path dependency").with_source(err))?;
+
+            for path in path_source
+                .list_files(&pkg)
                 .map_err(|err| Error::new("failed to list files").with_source(err))?
                 .into_iter()
                 .chain(once(pkg.manifest_path().to_path_buf()))
-                .filter_map(|path| {
-                    (path != pkg.manifest_path()).then(|| Self::read_generic_file(path))
+            {
+                files.push((format!("{}/{}", prefix, relative_path(root, &path)?), path));
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// List the files of the workspace that the dep-info files of the last
+    /// build of the package reference, like the files included with
+    /// `include_bytes!` or `include_str!`.
+    ///
+    /// Files outside of the workspace belong to external dependencies, and
+    /// files in the target directory are generated: both are ignored. The
+    /// dep-info files of binaries also list the files of the other packages
+    /// they are built from, which are covered by the hashes of these packages
+    /// and are ignored as well.
+    fn dep_info_files(
+        context: &Context,
+        package: &guppy::graph::PackageMetadata<'_>,
+        path_dependencies: &[(String, PathBuf)],
+    ) -> Result<Vec<PathBuf>> {
+        let target_root = context.target_root()?;
+        let artifacts_roots = Self::artifacts_roots(&target_root, &context.options().mode)?;
+        let package_root = package
+            .manifest_path()
+            .parent()
+            .map_or_else(PathBuf::new, |root| normalize_path(root.as_std_path()));
+        let other_roots: Vec<_> = context
+            .workspace_packages_metadata()
+            .filter(|package_metadata| package_metadata.id() != package.id())
+            .filter_map(|package_metadata| {
+                package_metadata
+                    .manifest_path()
+                    .parent()
+                    .map(|root| normalize_path(root.as_std_path()))
+            })
+            .chain(path_dependencies.iter().map(|(_, root)| root.clone()))
+            .collect();
+        let mut files = Vec::new();
+
+        for build_target in package.build_targets() {
+            let file_name = match build_target.id() {
+                guppy::graph::BuildTargetId::Library => {
+                    format!("lib{}.d", build_target.name().replace('-', "_"))
+                }
+                guppy::graph::BuildTargetId::Binary(name) => format!("{name}.d"),
+                _ => continue,
+            };
+
+            for artifacts_root in &artifacts_roots {
+                files.extend(
+                    dep_info::read_dep_info(&artifacts_root.join(&file_name))?
+                        .into_iter()
+                        .map(|path| normalize_path(&path))
+                        .filter(|path| {
+                            path.starts_with(context.workspace_root())
+                                && !path.starts_with(&target_root)
+                                && !is_owned_by_other(path, &package_root, &other_roots)
+                                && path.is_file()
+                        }),
+                );
+            }
+        }
+
+        files.sort();
+        files.dedup();
+
+        Ok(files)
+    }
+
+    /// List the directories that contain the artifacts built in the specified
+    /// mode: the one of the host and the ones of the targets that were cross
+    /// compiled for, like `target/x86_64-unknown-linux-musl/release`.
+    fn artifacts_roots(target_root: &Path, mode: &Mode) -> Result<Vec<PathBuf>> {
+        let mode = mode.to_string();
+        let mut roots = vec![target_root.join(&mode)];
+
+        let entries = match std::fs::read_dir(target_root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(roots),
+            Err(err) => {
+                return Err(Error::new("failed to read target directory")
+                    .with_source(err)
+                    .with_output(format!("Path: {}", target_root.display())))
+            }
+        };
+
+        for entry in entries {
+            let entry = entry
+                .map_err(|err| Error::new("failed to read target directory").with_source(err))?;
+            let root = entry.path().join(&mode);
+
+            if entry.file_name() != mode.as_str() && root.is_dir() {
+                roots.push(root);
+            }
+        }
+
+        roots.sort();
+
+        Ok(roots)
+    }
+
+    /// Resolve the files matched by the `hash_include` patterns.
+    ///
+    /// Patterns are relative to the package root and can point outside of it.
+    /// Directories are included recursively.
+    fn included_files(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for pattern in patterns {
+            let entries = glob::glob(&root.join(pattern).display().to_string())
+                .map_err(|err| {
+                    Error::new("failed to read glob pattern")
+                        .with_source(err)
+                        .with_explanation("The specified `hash_include` pattern could not be parsed. You may want to double-check for syntax errors.")
+                        .with_output(format!("Pattern: {pattern}"))
+                })?;
+
+            for entry in entries {
+                let path = entry
+                    .map_err(|err| Error::new("failed to resolve glob entry").with_source(err))?;
+
+                if path.is_dir() {
+                    for entry in walkdir::WalkDir::new(&path) {
+                        let entry = entry.map_err(|err| {
+                            Error::new("failed to walk directory").with_source(err)
+                        })?;
+
+                        // Symbolic links are not followed, but recorded as such.
+                        if !entry.file_type().is_dir() {
+                            files.push(entry.into_path());
+                        }
+                    }
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Parse the `hash_exclude` patterns.
+    ///
+    /// Patterns are matched against the paths relative to the package root.
+    fn exclude_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern).map_err(|err| {
+                    Error::new("failed to read glob pattern")
+                        .with_source(err)
+                        .with_explanation("The specified `hash_exclude` pattern could not be parsed. You may want to double-check for syntax errors.")
+                        .with_output(format!("Pattern: {pattern}"))
                 })
-                .collect::<Result<Vec<(PathBuf, Vec<u8>)>>>()?
-                .into_iter()
-                .collect(),
-        ))
+            })
+            .collect()
     }
 
+    /// Check whether the specified path is part of the sources.
+    ///
+    /// The path can be either absolute or relative to the package root.
     pub fn contains(&self, path: &Path) -> bool {
-        self.0.contains_key(path)
+        self.key(path).is_some()
+    }
+
+    /// Get the key of the specified path, if it is part of the sources.
+    ///
+    /// The path can be either absolute or relative to the package root.
+    pub fn key(&self, path: &Path) -> Option<String> {
+        let path = normalize_path(&self.root.join(path));
+
+        let key = match self
+            .path_dependencies
+            .iter()
+            .find(|(_, root)| path.starts_with(root))
+        {
+            Some((prefix, root)) => format!("{}/{}", prefix, relative_path(root, &path).ok()?),
+            None => relative_path(&self.root, &path).ok()?,
+        };
+
+        if self.files.contains_key(&key) {
+            Some(key)
+        } else {
+            None
+        }
+    }
+
+    /// Get the files, keyed by their normalized path relative to the package
+    /// root.
+    pub fn files(&self) -> &BTreeMap<String, SourceFile> {
+        &self.files
+    }
+
+    fn source_file(
+        path: &Path,
+        options: DigestOptions,
+        hash_cache: &HashCache,
+        git_file_modes: &GitFileModes,
+    ) -> Result<SourceFile> {
+        let metadata = std::fs::symlink_metadata(path)
+            .map_err(|err| Error::new("failed to read file metadata").with_source(err))
+            .with_context(format!("failed to digest `{}`", path.display()))?;
+
+        if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(path)
+                .map_err(|err| Error::new("failed to read symbolic link").with_source(err))
+                .with_context(format!("failed to digest `{}`", path.display()))?;
+
+            return Ok(SourceFile::Symlink {
+                target: target.to_string_lossy().replace('\\', "/"),
+            });
+        }
+
+        Ok(SourceFile::File {
+            digest: hash_cache.digest_file(path, options)?,
+            executable: if cfg!(unix) {
+                is_executable(&metadata)
+            } else {
+                git_file_modes.is_executable(path)
+            },
+        })
+    }
+
+    /// Compute the digest of a file.
+    ///
+    /// The file is streamed rather than read in memory at once, so that large
+    /// files do not cause excessive memory usage.
+    pub(crate) fn digest_file(path: &Path, options: DigestOptions) -> Result<String> {
+        let file = std::fs::File::open(path)
+            .map_err(|err| Error::new("failed to open file").with_source(err))
+            .with_context(format!("failed to digest `{}`", path.display()))?;
+
+        let mut reader = std::io::BufReader::new(file);
+        let mut hasher = options.algorithm.hasher();
+
+        let normalize = options.normalize_line_endings
+            && reader
+                .fill_buf()
+                .map(is_text)
+                .map_err(|err| Error::new("failed to read file").with_source(err))
+                .with_context(format!("failed to digest `{}`", path.display()))?;
+
+        if normalize {
+            copy_normalizing_line_endings(&mut reader, &mut hasher)
+        } else {
+            std::io::copy(&mut reader, &mut hasher).map(|_| ())
+        }
+        .map_err(|err| Error::new("failed to read file").with_source(err))
+        .with_context(format!("failed to digest `{}`", path.display()))?;
+
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// The executable bits of the files tracked by Git.
+///
+/// File systems without an executable bit - like on Windows - rely on the
+/// mode recorded in the Git index instead, so that executable files hash the
+/// same as on Unix.
+#[derive(Debug, Default)]
+pub(crate) struct GitFileModes {
+    executable_files: HashSet<PathBuf>,
+}
+
+impl GitFileModes {
+    /// Read the modes of the files tracked in the Git repository that contains
+    /// the specified directory, if any.
+    pub(crate) fn load(root: &Path) -> Result<Self> {
+        let repo = match git2::Repository::discover(root) {
+            Ok(repo) => repo,
+            Err(err) => {
+                debug!("No Git repository to read file modes from: {}", err);
+
+                return Ok(Self::default());
+            }
+        };
+
+        let workdir = match repo.workdir() {
+            Some(workdir) => normalize_path(workdir),
+            None => return Ok(Self::default()),
+        };
+
+        let index = repo
+            .index()
+            .map_err(|err| Error::new("failed to read Git index").with_source(err))?;
+
+        let executable_files = index
+            .iter()
+            .filter(|entry| entry.mode == GIT_EXECUTABLE_MODE)
+            .map(|entry| workdir.join(String::from_utf8_lossy(&entry.path).as_ref()))
+            .map(|path| normalize_path(&path))
+            .collect();
+
+        Ok(Self { executable_files })
+    }
+
+    /// Check whether the specified file is tracked as executable.
+    pub(crate) fn is_executable(&self, path: &Path) -> bool {
+        self.executable_files.contains(&normalize_path(path))
+    }
+}
+
+/// The mode of executable files in the Git index.
+const GIT_EXECUTABLE_MODE: u32 = 0o100_755;
+
+/// The number of leading bytes inspected to tell text files from binary ones.
+const TEXT_DETECTION_LENGTH: usize = 8000;
+
+/// Tell whether the specified leading bytes of a file denote a text file.
+///
+/// Like Git, a file is considered binary if it contains a NUL byte in its
+/// first 8000 bytes.
+fn is_text(buf: &[u8]) -> bool {
+    !buf[..buf.len().min(TEXT_DETECTION_LENGTH)].contains(&0)
+}
+
+/// Copy `reader` into `writer`, replacing CRLF line endings with LF.
+///
+/// Lone CR characters are kept as-is. A CR at the end of a chunk is held back
+/// until the next chunk tells whether it is part of a CRLF sequence.
+fn copy_normalizing_line_endings(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut pending_cr = false;
+    let mut output = Vec::new();
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            break;
+        }
+
+        output.clear();
+
+        for &byte in buf {
+            if pending_cr && byte != b'\n' {
+                output.push(b'\r');
+            }
+
+            pending_cr = byte == b'\r';
+
+            if !pending_cr {
+                output.push(byte);
+            }
+        }
+
+        writer.write_all(&output)?;
+
+        let len = buf.len();
+        reader.consume(len);
+    }
+
+    if pending_cr {
+        writer.write_all(b"\r")?;
+    }
+
+    Ok(())
+}
+
+/// Check whether the specified path belongs to one of `other_roots` rather
+/// than to `root`, that is whether the deepest of these roots that contains
+/// the path is not `root`.
+fn is_owned_by_other(path: &Path, root: &Path, other_roots: &[PathBuf]) -> bool {
+    let depth = if path.starts_with(root) {
+        root.components().count()
+    } else {
+        0
+    };
+
+    other_roots
+        .iter()
+        .any(|other_root| path.starts_with(other_root) && other_root.components().count() > depth)
+}
+
+/// Compute the normalized path of `path` relative to `root`.
+///
+/// The resulting path always uses `/` as a separator and starts with `..`
+/// components if `path` is outside of `root`. `path` can be relative, in which
+/// case it is considered relative to `root` already.
+pub(crate) fn relative_path(root: &Path, path: &Path) -> Result<String> {
+    let root = normalize_path(root);
+    let path = normalize_path(&root.join(path));
+
+    let root_components: Vec<_> = root.components().collect();
+    let path_components: Vec<_> = path.components().collect();
+
+    let common_components = root_components
+        .iter()
+        .zip(&path_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    if common_components == 0 {
+        return Err(
+            Error::new("path has no common root with the package root").with_output(format!(
+                "root: {}\npath: {}",
+                root.display(),
+                path.display()
+            )),
+        );
+    }
+
+    Ok(
+        std::iter::repeat_n("..".into(), root_components.len() - common_components)
+            .chain(
+                path_components[common_components..]
+                    .iter()
+                    .map(|component| component.as_os_str().to_string_lossy()),
+            )
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Lexically normalize a path, removing `.` and resolving `..` components.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            Component::Normal(_) | Component::RootDir | Component::Prefix(_) => {
+                result.push(component);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_workspace::TestWorkspace;
+    use std::io::Write;
+
+    #[test]
+    fn test_relative_path() {
+        let root = std::env::temp_dir().join("package");
+
+        assert_eq!(
+            relative_path(&root, &root.join("src").join("lib.rs")).unwrap(),
+            "src/lib.rs"
+        );
+        assert_eq!(
+            relative_path(&root, Path::new("./src/main.rs")).unwrap(),
+            "src/main.rs"
+        );
+
+        assert_eq!(
+            relative_path(&root, &root.join("..").join("proto").join("a.proto")).unwrap(),
+            "../proto/a.proto"
+        );
+        assert_eq!(
+            relative_path(&root, Path::new("../../schemas/x.json")).unwrap(),
+            "../../schemas/x.json"
+        );
+        assert_eq!(
+            relative_path(&root, &std::env::temp_dir().join("other").join("lib.rs")).unwrap(),
+            "../other/lib.rs"
+        );
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-monorepo-sources-{}-{}",
+            name,
+            std::process::id()
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    const SHA256: DigestOptions = DigestOptions {
+        algorithm: HashAlgorithm::Sha256,
+        normalize_line_endings: false,
+    };
+
+    #[test]
+    fn test_digest_file() {
+        let dir = temp_dir("digest-file");
+        let path = dir.join("data.bin");
+
+        // Larger than the buffer, so that the file is streamed in chunks.
+        let data: Vec<u8> = (0..100_000_u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.write_all(&data).unwrap();
+        assert_eq!(
+            Sources::digest_file(&path, SHA256).unwrap(),
+            hasher.finalize()
+        );
+
+        let blake3 = DigestOptions {
+            algorithm: HashAlgorithm::Blake3,
+            ..SHA256
+        };
+        assert_eq!(
+            Sources::digest_file(&path, blake3).unwrap(),
+            blake3::hash(&data).to_hex().to_string()
+        );
+
+        std::fs::write(&path, b"hello\r\nworld\r\n").unwrap();
+        let normalized = DigestOptions {
+            normalize_line_endings: true,
+            ..SHA256
+        };
+        std::fs::write(dir.join("lf.txt"), b"hello\nworld\n").unwrap();
+        assert_eq!(
+            Sources::digest_file(&path, normalized).unwrap(),
+            Sources::digest_file(&dir.join("lf.txt"), SHA256).unwrap()
+        );
+
+        assert!(Sources::digest_file(&dir.join("missing"), SHA256).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_source_files() {
+        let dir = temp_dir("source-files");
+
+        let paths: BTreeMap<_, _> = (0..64)
+            .map(|i| {
+                let path = dir.join(format!("{i}.txt"));
+                std::fs::write(&path, format!("file {i}")).unwrap();
+
+                (format!("{i}.txt"), path)
+            })
+            .collect();
+
+        let files = Sources::source_files(
+            paths.clone(),
+            SHA256,
+            &HashCache::disabled(),
+            &GitFileModes::default(),
+        )
+        .unwrap();
+
+        assert_eq!(files.len(), paths.len());
+
+        for (key, path) in &paths {
+            assert_eq!(
+                files[key],
+                SourceFile::File {
+                    digest: Sources::digest_file(path, SHA256).unwrap(),
+                    executable: false,
+                }
+            );
+        }
+
+        // A single failure fails the whole computation.
+        let mut paths = paths;
+        paths.insert("missing.txt".to_string(), dir.join("missing.txt"));
+        assert!(Sources::source_files(
+            paths,
+            SHA256,
+            &HashCache::disabled(),
+            &GitFileModes::default(),
+        )
+        .is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_external_files() {
+        let workspace = TestWorkspace::new("external-files");
+        // Path dependencies inside of the workspace directory are members of
+        // the workspace: this one lives next to it.
+        let vendored_name = format!(
+            "{}-vendored",
+            workspace.root().file_name().unwrap().to_string_lossy()
+        );
+        let vendored = format!("../{vendored_name}");
+        workspace
+            .write(
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"packages/*\"]\nresolver = \"2\"\n\n\
+                 [workspace.metadata.monorepo]\ndep_info_sources = true\n",
+            )
+            .add_package("api", &[], "")
+            .add_package("service", &["api"], "")
+            .write(
+                "packages/service/Cargo.toml",
+                &format!(
+                    "[package]\nname = \"service\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                     [dependencies]\napi = {{ path = \"../api\" }}\n\
+                     vendored = {{ path = \"../../../{vendored_name}\" }}\n"
+                ),
+            )
+            .write(
+                &format!("{vendored}/Cargo.toml"),
+                "[package]\nname = \"vendored\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            )
+            .write(&format!("{vendored}/src/lib.rs"), "")
+            .write("assets/logo.svg", "<svg/>")
+            .write("assets/logo-musl.svg", "<svg/>");
+
+        let root = workspace.root();
+        workspace.write(
+            "target/debug/libservice.d",
+            &format!(
+                "{}: {} {} {}\n",
+                root.join("target/debug/libservice.rlib").display(),
+                root.join("packages/service/src/lib.rs").display(),
+                root.join("packages/api/src/lib.rs").display(),
+                root.join("assets/logo.svg").display(),
+            ),
+        );
+        // Cross-compiled artifacts live in a directory named after their
+        // target.
+        workspace.write(
+            "target/x86_64-unknown-linux-musl/debug/libservice.d",
+            &format!(
+                "{}: {} {}\n",
+                root.join("target/x86_64-unknown-linux-musl/debug/libservice.rlib")
+                    .display(),
+                root.join("packages/service/src/lib.rs").display(),
+                root.join("assets/logo-musl.svg").display(),
+            ),
+        );
+
+        let context = workspace.context();
+        let package = context
+            .packages()
+            .unwrap()
+            .into_iter()
+            .find(|package| package.name() == "service")
+            .unwrap();
+        let sources = package.sources();
+
+        assert_eq!(
+            sources.files().keys().collect::<Vec<_>>(),
+            [
+                "../../assets/logo-musl.svg",
+                "../../assets/logo.svg",
+                "path-dependency:vendored/Cargo.toml",
+                "path-dependency:vendored/src/lib.rs",
+                "src/lib.rs",
+            ]
+        );
+
+        assert_eq!(
+            sources.key(&root.join(&vendored).join("src/lib.rs")),
+            Some("path-dependency:vendored/src/lib.rs".to_string())
+        );
+        assert_eq!(
+            sources.key(Path::new("../../assets/logo.svg")),
+            Some("../../assets/logo.svg".to_string())
+        );
+        assert!(!sources.contains(&root.join("packages/api/src/lib.rs")));
+
+        std::fs::remove_dir_all(root.join(&vendored)).ok();
     }
 
-    pub fn read_generic_file(path: PathBuf) -> Result<(PathBuf, Vec<u8>)> {
-        std::fs::read(&path)
-            .map(|bytes| (path, bytes))
-            .map_err(|err| Error::new("failed to read file").with_source(err))
+    #[test]
+    fn test_hash_include_and_exclude() {
+        let workspace = TestWorkspace::new("hash-include-exclude");
+        workspace
+            .add_package(
+                "service",
+                &[],
+                "hash_include = [\"../../proto/*.proto\", \"../../schemas\"]\n\
+                 hash_exclude = [\"docs/**\", \"**/*.snap\"]",
+            )
+            .write("proto/service.proto", "")
+            .write("proto/README.md", "")
+            .write("schemas/service.json", "{}")
+            .write("schemas/v1/service.json", "{}")
+            .write("packages/service/docs/guide.md", "")
+            .write("packages/service/src/snapshots/lib.snap", "")
+            .write("packages/service/build.rs", "fn main() {}");
+
+        let context = workspace.context();
+        let service = context.resolve_package_by_name("service").unwrap();
+
+        assert_eq!(
+            service.sources().files().keys().collect::<Vec<_>>(),
+            [
+                "../../proto/service.proto",
+                "../../schemas/service.json",
+                "../../schemas/v1/service.json",
+                "build.rs",
+                "src/lib.rs",
+            ]
+        );
+
+        workspace.add_package("service", &[], "hash_exclude = [\"[\"]");
+
+        assert_eq!(
+            workspace
+                .context_builder()
+                .build()
+                .and_then(|context| context.resolve_package_by_name("service").map(|_| ()))
+                .map_err(|err| err.description().to_string()),
+            Err("failed to read glob pattern".to_string())
+        );
+    }
+
+    #[test]
+    fn test_copy_normalizing_line_endings() {
+        let normalize = |data: &[u8], capacity| {
+            let mut reader = std::io::BufReader::with_capacity(capacity, data);
+            let mut output = Vec::new();
+
+            copy_normalizing_line_endings(&mut reader, &mut output).unwrap();
+
+            output
+        };
+
+        let data = b"a\r\nb\rc\n\r\r\nd\r";
+
+        // A capacity of 1 puts every CR at a chunk boundary.
+        for capacity in [1, 2, 3, 8192] {
+            assert_eq!(normalize(data, capacity), b"a\nb\rc\n\r\nd\r");
+        }
+    }
+
+    #[test]
+    fn test_git_file_modes() {
+        let dir = temp_dir("git-file-modes");
+        let repo = git2::Repository::init(&dir).unwrap();
+
+        std::fs::write(dir.join("run.sh"), "#!/bin/sh\n").unwrap();
+        std::fs::write(dir.join("README.md"), "").unwrap();
+        std::fs::write(dir.join("untracked.sh"), "#!/bin/sh\n").unwrap();
+
+        // Record the modes in the index directly, as the file system may not
+        // have an executable bit.
+        let mut index = repo.index().unwrap();
+        for (path, mode) in [("run.sh", GIT_EXECUTABLE_MODE), ("README.md", 0o100_644)] {
+            index.add_path(Path::new(path)).unwrap();
+
+            let mut entry = index.get_path(Path::new(path), 0).unwrap();
+            entry.mode = mode;
+            index.add(&entry).unwrap();
+        }
+        index.write().unwrap();
+
+        let modes = GitFileModes::load(&dir).unwrap();
+
+        assert!(modes.is_executable(&dir.join("run.sh")));
+        assert!(modes.is_executable(&dir.join("src").join("..").join("run.sh")));
+        assert!(!modes.is_executable(&dir.join("README.md")));
+        assert!(!modes.is_executable(&dir.join("untracked.sh")));
+
+        std::fs::remove_dir_all(dir.join(".git")).unwrap();
+
+        // Outside of a Git repository, no file is executable.
+        let modes = GitFileModes::load(&dir).unwrap();
+        assert!(!modes.is_executable(&dir.join("run.sh")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_is_text() {
+        assert!(is_text(b"fn main() {}\r\n"));
+        assert!(is_text(b""));
+        assert!(!is_text(b"\x89PNG\r\n\x1a\n\0\0"));
+
+        let mut data = vec![b'a'; TEXT_DETECTION_LENGTH];
+        data.push(0);
+        assert!(is_text(&data));
     }
 }
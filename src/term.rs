@@ -34,22 +34,32 @@ pub fn print_step(color: Color, action: &str, description: impl Display) {
     }
 }
 
-/// Prints an action step, with a green action verb followed by the subject.
+/// Reports an action step, printed as a green action verb followed by the
+/// subject.
 #[macro_export]
 macro_rules! action_step {
     ($action:expr, $description:expr $(,)?) => {
-        $crate::term::print_step($crate::term::ACTION_STEP_COLOR, $action, $description)
+        $crate::reporter::step(
+            $crate::reporter::StepKind::Action,
+            $action,
+            $description,
+        )
     };
     ($action:expr, $fmt:expr, $($arg:tt)*) => {
         action_step!($action, format!($fmt, $($arg)*))
     };
 }
 
-/// Prints an ignore step, with a yellow action verb followed by the subject.
+/// Reports an ignore step, printed as a yellow action verb followed by the
+/// subject.
 #[macro_export]
 macro_rules! ignore_step {
     ($action:expr, $description:expr $(,)?) => {
-        $crate::term::print_step($crate::term::IGNORE_STEP_COLOR, $action, $description)
+        $crate::reporter::step(
+            $crate::reporter::StepKind::Ignore,
+            $action,
+            $description,
+        )
     };
     ($action:expr, $fmt:expr, $($arg:tt)*) => {
         ignore_step!($action, format!($fmt, $($arg)*))
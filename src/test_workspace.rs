@@ -0,0 +1,114 @@
+//! Temporary Cargo workspaces for the tests that need a real package graph.
+
+use std::path::{Path, PathBuf};
+
+use crate::{Context, ContextBuilder, Options};
+
+/// A Cargo workspace in a temporary directory, removed when dropped.
+///
+/// Packages live in `packages/<name>` and are all members of the workspace.
+pub(crate) struct TestWorkspace {
+    root: PathBuf,
+}
+
+impl TestWorkspace {
+    /// Create an empty workspace, with a name unique to the test.
+    pub(crate) fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("cargo-monorepo-{}-{}", name, std::process::id()));
+
+        std::fs::remove_dir_all(&root).ok();
+
+        let workspace = Self { root };
+        workspace.write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"packages/*\"]\nresolver = \"2\"\n",
+        );
+
+        workspace
+    }
+
+    /// Add a library package depending on the specified workspace packages,
+    /// with the specified `package.metadata.monorepo` table content.
+    pub(crate) fn add_package(&self, name: &str, dependencies: &[&str], metadata: &str) -> &Self {
+        let dependencies: String = dependencies
+            .iter()
+            .map(|dependency| format!("{dependency} = {{ path = \"../{dependency}\" }}\n"))
+            .collect::<Vec<_>>()
+            .concat();
+
+        self.write(
+            &format!("packages/{name}/Cargo.toml"),
+            &format!(
+                "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                 [dependencies]\n{dependencies}\n[package.metadata.monorepo]\n{metadata}\n"
+            ),
+        );
+        self.write(&format!("packages/{name}/src/lib.rs"), "");
+
+        self
+    }
+
+    /// Write a file, relative to the workspace root.
+    pub(crate) fn write(&self, path: &str, contents: &str) -> &Self {
+        let path = self.root.join(path);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+
+        self
+    }
+
+    /// Commit all the files of the workspace, initializing its Git repository
+    /// if needed.
+    pub(crate) fn commit(&self, message: &str) -> &Self {
+        let repo = git2::Repository::open(&self.root)
+            .or_else(|_| git2::Repository::init(&self.root))
+            .unwrap();
+
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        self
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn context(&self) -> Context {
+        self.context_builder().build().unwrap()
+    }
+
+    pub(crate) fn context_builder(&self) -> ContextBuilder {
+        Context::builder()
+            .with_manifest_path(self.root.join("Cargo.toml"))
+            .with_options(Options {
+                no_cache: true,
+                ..Options::default()
+            })
+    }
+}
+
+impl Drop for TestWorkspace {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.root).ok();
+    }
+}
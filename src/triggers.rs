@@ -0,0 +1,171 @@
+//! Workspace-level trigger paths.
+//!
+//! Some files outside of any package - like CI workflows or a shared
+//! Dockerfile - still affect packages. Triggers map glob patterns of such
+//! files to the packages they affect.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    change_explanation::DirectChange, metadata::PackageSelector, sources::relative_path, Error,
+    Package, Result,
+};
+
+/// Patterns match paths relative to the workspace root, and `*` never matches
+/// a path separator: nested directories must be matched with `**`.
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+pub(crate) struct Triggers<'m> {
+    triggers: Vec<(glob::Pattern, &'m PackageSelector)>,
+}
+
+impl<'m> Triggers<'m> {
+    pub(crate) fn new(triggers: &'m BTreeMap<String, PackageSelector>) -> Result<Self> {
+        triggers
+            .iter()
+            .map(|(pattern, selector)| {
+                glob::Pattern::new(pattern)
+                    .map(|pattern| (pattern, selector))
+                    .map_err(|err| {
+                        Error::new("failed to read glob pattern")
+                            .with_source(err)
+                            .with_explanation("The specified trigger pattern could not be parsed. You may want to double-check for syntax errors.")
+                            .with_output(format!("Pattern: {pattern}"))
+                    })
+            })
+            .collect::<Result<_>>()
+            .map(|triggers| Self { triggers })
+    }
+
+    /// Get the packages affected by the triggers that match the changed
+    /// files, along with the trigger that affects them.
+    pub(crate) fn changed_packages<'a, 'g>(
+        &self,
+        workspace_root: &Path,
+        changed_files: &[PathBuf],
+        packages: &'a [Package<'g>],
+    ) -> Result<Vec<(&'a Package<'g>, DirectChange)>> {
+        let changed_files = changed_files
+            .iter()
+            .map(|changed_file| relative_path(workspace_root, changed_file))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut result = Vec::new();
+
+        for (pattern, selector) in &self.triggers {
+            let files: Vec<_> = changed_files
+                .iter()
+                .filter(|changed_file| pattern.matches_with(changed_file, MATCH_OPTIONS))
+                .cloned()
+                .collect();
+
+            if files.is_empty() {
+                continue;
+            }
+
+            for package in selector.select(packages)? {
+                result.push((
+                    package,
+                    DirectChange::Trigger {
+                        pattern: pattern.to_string(),
+                        files: files.clone(),
+                    },
+                ));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_workspace::TestWorkspace, Context};
+
+    fn changed_packages(
+        context: &Context,
+        triggers: &BTreeMap<String, PackageSelector>,
+        changed_files: &[&str],
+    ) -> Vec<(String, Vec<String>)> {
+        let packages = context.packages().unwrap();
+        let changed_files: Vec<_> = changed_files
+            .iter()
+            .map(|path| context.workspace_root().join(path))
+            .collect();
+
+        Triggers::new(triggers)
+            .unwrap()
+            .changed_packages(context.workspace_root(), &changed_files, &packages)
+            .unwrap()
+            .into_iter()
+            .map(|(package, change)| match change {
+                DirectChange::Trigger { files, .. } => (package.name().to_string(), files),
+                change => panic!("unexpected change: {change:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_changed_packages() {
+        let workspace = TestWorkspace::new("triggers");
+        workspace
+            .add_package("api", &[], "groups = [\"backend\"]")
+            .add_package("web", &[], "");
+        let context = workspace.context();
+
+        let triggers: BTreeMap<String, PackageSelector> = toml::from_str(
+            r#"
+                ".github/workflows/*" = "all"
+                "proto/**" = { groups = ["backend"] }
+                "docker/Dockerfile" = "web"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            changed_packages(&context, &triggers, &[".github/workflows/ci.yml"]),
+            [
+                (
+                    "api".to_string(),
+                    vec![".github/workflows/ci.yml".to_string()]
+                ),
+                (
+                    "web".to_string(),
+                    vec![".github/workflows/ci.yml".to_string()]
+                ),
+            ]
+        );
+
+        // `*` does not cross directories.
+        assert!(
+            changed_packages(&context, &triggers, &[".github/workflows/old/ci.yml"]).is_empty()
+        );
+
+        assert_eq!(
+            changed_packages(
+                &context,
+                &triggers,
+                &[
+                    "proto/billing/v1/invoice.proto",
+                    "docker/Dockerfile",
+                    "README.md"
+                ]
+            ),
+            [
+                ("web".to_string(), vec!["docker/Dockerfile".to_string()]),
+                (
+                    "api".to_string(),
+                    vec!["proto/billing/v1/invoice.proto".to_string()]
+                ),
+            ]
+        );
+    }
+}